@@ -0,0 +1,262 @@
+//! Associated token accounts: address derivation, validation, and
+//! creation via the associated token account program. Enabled by the
+//! `token` feature.
+
+use {
+    crate::{
+        cpi::{
+            impl_invoke, invoke_signed_checked, AccountMetaRef,
+            InstructionRef, Signers,
+        },
+        pda::find_program_address,
+        token::check_token_program,
+        NoStdAccountInfo,
+    },
+    solana_program_error::{ProgramError, ProgramResult},
+    solana_pubkey::Pubkey,
+};
+
+/// The associated token account program id.
+pub const ID: Pubkey = solana_pubkey::pubkey!(
+    "ATokenGPvbdGVxr1b2hvZbsiqW5xWH25efTNsLJA8knL"
+);
+
+/// Derives the associated token account address of `wallet` for
+/// `mint` under `token_program`, along with its bump seed.
+#[inline]
+pub fn derive(
+    wallet: &Pubkey,
+    mint: &Pubkey,
+    token_program: &Pubkey,
+) -> (Pubkey, u8) {
+    find_program_address(
+        &[wallet.as_ref(), token_program.as_ref(), mint.as_ref()],
+        &ID,
+    )
+}
+
+/// Checks that `account` is the associated token account of `wallet`
+/// for `mint` under `token_program`, whether or not it exists yet.
+///
+/// Fails with [`ProgramError::IncorrectProgramId`] if `token_program`
+/// isn't a token program, and with [`ProgramError::InvalidSeeds`] if
+/// the address doesn't match. Without a known `bump` this searches for
+/// the bump seed, so it isn't cheap.
+pub fn expect(
+    account: &NoStdAccountInfo,
+    wallet: &Pubkey,
+    mint: &Pubkey,
+    token_program: &Pubkey,
+    bump: Option<u8>,
+) -> Result<(), ProgramError> {
+    check_token_program(token_program)?;
+    let address = match bump {
+        Some(bump) => {
+            return account.expect_pda(
+                &[
+                    wallet.as_ref(),
+                    token_program.as_ref(),
+                    mint.as_ref(),
+                ],
+                bump,
+                &ID,
+            )
+        }
+        None => derive(wallet, mint, token_program).0,
+    };
+    if address != *account.key() {
+        return Err(ProgramError::InvalidSeeds);
+    }
+    Ok(())
+}
+
+/// Private: invokes the associated token account program to create
+/// `account`, with discriminant `discriminant`.
+#[inline(always)]
+fn invoke_create(
+    accounts: [&NoStdAccountInfo; 6],
+    discriminant: u8,
+    signers: &Signers,
+) -> ProgramResult {
+    let [payer, account, wallet, mint, system_program, token_program] =
+        accounts;
+    check_token_program(token_program.key())?;
+
+    let metas = [
+        AccountMetaRef::writable_signer(payer.key()),
+        AccountMetaRef::writable(account.key()),
+        AccountMetaRef::readonly(wallet.key()),
+        AccountMetaRef::readonly(mint.key()),
+        AccountMetaRef::readonly(system_program.key()),
+        AccountMetaRef::readonly(token_program.key()),
+    ];
+    let data = [discriminant];
+    let instruction = InstructionRef::new(&ID, &metas, &data);
+    invoke_signed_checked(&instruction, accounts, signers)
+}
+
+/// Creates the associated token account of `wallet` for `mint`,
+/// failing if it already exists.
+pub struct Create<'a> {
+    /// Funds the new account. Writable signer.
+    pub payer: &'a NoStdAccountInfo,
+    /// The associated token account. Writable.
+    pub account: &'a NoStdAccountInfo,
+    /// Owner of the new account.
+    pub wallet: &'a NoStdAccountInfo,
+    /// The mint.
+    pub mint: &'a NoStdAccountInfo,
+    /// The system program.
+    pub system_program: &'a NoStdAccountInfo,
+    /// The token program owning the mint.
+    pub token_program: &'a NoStdAccountInfo,
+}
+
+impl_invoke!(Create);
+
+impl Create<'_> {
+    #[inline]
+    pub fn invoke_signed(&self, signers: &Signers) -> ProgramResult {
+        invoke_create(
+            [
+                self.payer,
+                self.account,
+                self.wallet,
+                self.mint,
+                self.system_program,
+                self.token_program,
+            ],
+            0,
+            signers,
+        )
+    }
+}
+
+/// Creates the associated token account of `wallet` for `mint`, doing
+/// nothing if it already exists with the right owner.
+pub struct CreateIdempotent<'a> {
+    /// Funds the new account. Writable signer.
+    pub payer: &'a NoStdAccountInfo,
+    /// The associated token account. Writable.
+    pub account: &'a NoStdAccountInfo,
+    /// Owner of the new account.
+    pub wallet: &'a NoStdAccountInfo,
+    /// The mint.
+    pub mint: &'a NoStdAccountInfo,
+    /// The system program.
+    pub system_program: &'a NoStdAccountInfo,
+    /// The token program owning the mint.
+    pub token_program: &'a NoStdAccountInfo,
+}
+
+impl_invoke!(CreateIdempotent);
+
+impl CreateIdempotent<'_> {
+    #[inline]
+    pub fn invoke_signed(&self, signers: &Signers) -> ProgramResult {
+        invoke_create(
+            [
+                self.payer,
+                self.account,
+                self.wallet,
+                self.mint,
+                self.system_program,
+                self.token_program,
+            ],
+            1,
+            signers,
+        )
+    }
+}
+
+#[test]
+fn test_ata() {
+    use crate::{cpi::take_invoke, entrypoint_nostd::TestAccount};
+
+    let wallet = Pubkey::new_from_array([1; 32]);
+    let mint = Pubkey::new_from_array([2; 32]);
+    let token_program = crate::token::ID;
+
+    let (address, bump) = derive(&wallet, &mint, &token_program);
+    assert_eq!(
+        (address, bump),
+        Pubkey::find_program_address(
+            &[wallet.as_ref(), token_program.as_ref(), mint.as_ref()],
+            &ID
+        )
+    );
+
+    let (_buffer, account) = TestAccount {
+        owner: token_program,
+        ..Default::default()
+    }
+    .build();
+    assert_eq!(
+        expect(&account, &wallet, &mint, &token_program, None),
+        Err(ProgramError::InvalidSeeds)
+    );
+    assert_eq!(
+        expect(&account, &wallet, &mint, &token_program, Some(bump)),
+        Err(ProgramError::InvalidSeeds)
+    );
+
+    // Doesn't exist yet, so not owned by a token program
+    let (_buffer, account) = TestAccount {
+        key: address,
+        owner: Pubkey::default(),
+        ..Default::default()
+    }
+    .build();
+    assert_eq!(
+        expect(&account, &wallet, &mint, &token_program, None),
+        Ok(())
+    );
+    assert_eq!(
+        expect(&account, &wallet, &mint, &token_program, Some(bump)),
+        Ok(())
+    );
+    assert_eq!(
+        expect(&account, &wallet, &mint, &wallet, None),
+        Err(ProgramError::IncorrectProgramId)
+    );
+    assert_eq!(
+        expect(&account, &mint, &wallet, &token_program, None),
+        Err(ProgramError::InvalidSeeds)
+    );
+    assert_eq!(
+        expect(&account, &wallet, &mint, &crate::token_2022::ID, None),
+        Err(ProgramError::InvalidSeeds)
+    );
+
+    let (_buffers, [payer, wallet, mint, system_program]) =
+        crate::entrypoint_nostd::test_accounts();
+    let (_buffer, token_program) = TestAccount {
+        key: token_program,
+        ..Default::default()
+    }
+    .build();
+    CreateIdempotent {
+        payer: &payer,
+        account: &account,
+        wallet: &wallet,
+        mint: &mint,
+        system_program: &system_program,
+        token_program: &token_program,
+    }
+    .invoke()
+    .unwrap();
+    let invoked = take_invoke().unwrap();
+    assert_eq!(invoked.program_id, ID);
+    assert_eq!(
+        invoked.accounts,
+        [
+            (*payer.key(), true, true),
+            (*account.key(), true, false),
+            (*wallet.key(), false, false),
+            (*mint.key(), false, false),
+            (*system_program.key(), false, false),
+            (*token_program.key(), false, false),
+        ]
+    );
+    assert_eq!(invoked.data, [1]);
+}
@@ -0,0 +1,232 @@
+//! Bridges [`NoStdAccountInfo`]s to and from `solana_program`'s
+//! `AccountInfo`, for crates that still take or hand out the latter.
+//! Enabled by the `account-info-compat` feature.
+//!
+//! Both point straight into the serialized input, so writes to
+//! lamports and data (including reallocs) through one are seen by the
+//! other afterwards. The converted infos only live for the duration of
+//! a closure, since their `Rc`s or borrow state live on the stack.
+
+use {
+    crate::{
+        try_borrow_mut_data_many, NoStdAccountInfo, RcRefCellInner,
+        ALIASED_ACCOUNTS_ERROR,
+    },
+    solana_account_info::AccountInfo,
+    solana_program_error::ProgramError,
+    solana_pubkey::Pubkey,
+};
+
+impl NoStdAccountInfo {
+    /// Calls `f` with an `AccountInfo` for this account.
+    ///
+    /// See [`with_account_infos`].
+    pub fn with_account_info<R>(
+        &self,
+        f: impl FnOnce(&AccountInfo<'_>) -> R,
+    ) -> Result<R, ProgramError> {
+        with_account_infos([self], |[info]| f(info))
+    }
+}
+
+/// Calls `f` with an `AccountInfo` for each of `accounts`.
+///
+/// The lamports and data of every account stay mutably borrowed until
+/// `f` returns, so that the `AccountInfo`s' `RefCell`s are the only way
+/// to reach them. This fails if any of them is already borrowed, or if
+/// two of the accounts are the same account.
+///
+/// `f` must work for any lifetime of the `AccountInfo`s, so neither
+/// they nor their `Rc`s can escape it.
+pub fn with_account_infos<const N: usize, R>(
+    accounts: [&NoStdAccountInfo; N],
+    f: impl FnOnce(&[AccountInfo<'_>; N]) -> R,
+) -> Result<R, ProgramError> {
+    let _data_guards = try_borrow_mut_data_many(accounts)?;
+    let lamport_guards =
+        accounts.map(|account| account.try_borrow_mut_lamports());
+    if lamport_guards
+        .iter()
+        .any(Result::is_err)
+    {
+        return Err(ProgramError::AccountBorrowFailed);
+    }
+
+    // The guards above keep everyone else out while these are alive
+    let cells = accounts.map(|account| unsafe {
+        (
+            RcRefCellInner::new(
+                account.unchecked_borrow_mut_lamports(),
+            ),
+            RcRefCellInner::new(account.unchecked_borrow_mut_data()),
+        )
+    });
+
+    // SAFETY: each cell gets exactly one Rc, and all of them (along
+    // with any clones made by `f`) are dropped before the cells.
+    let infos = core::array::from_fn(|i| {
+        let (lamports, data) = &cells[i];
+        AccountInfo {
+            key: accounts[i].key(),
+            lamports: unsafe { lamports.as_rcrc() },
+            data: unsafe { data.as_rcrc() },
+            owner: accounts[i].owner(),
+            rent_epoch: u64::MAX,
+            is_signer: accounts[i].is_signer(),
+            is_writable: accounts[i].is_writable(),
+            executable: accounts[i].executable(),
+        }
+    });
+
+    // Locals drop in reverse order: the infos, then the cells, then the
+    // guards
+    Ok(f(&infos))
+}
+
+/// Calls `f` with a `NoStdAccountInfo` for each of `infos`, the reverse
+/// of [`with_account_infos`].
+///
+/// The lamports and data `RefCell`s of every info stay mutably
+/// borrowed until `f` returns, so that the `NoStdAccountInfo`s are the
+/// only way to reach them, and their own borrow tracking starts out
+/// clear. This fails if any of them is already borrowed, or if two of
+/// the infos are the same account. Data reallocated through the
+/// `NoStdAccountInfo`s is resized in the `AccountInfo`s afterwards.
+///
+/// # Safety
+/// Every info's `key` must point into the serialized program input, as
+/// it does for the `AccountInfo`s `solana_program`'s entrypoint and
+/// [`with_account_infos`] hand out.
+pub unsafe fn with_no_std_account_infos<const N: usize, R>(
+    infos: [&AccountInfo<'_>; N],
+    f: impl FnOnce(&[NoStdAccountInfo; N]) -> R,
+) -> Result<R, ProgramError> {
+    let accounts = infos.map(|info| {
+        NoStdAccountInfo::from_key_ptr(info.key as *const Pubkey)
+    });
+    for (i, account) in accounts.iter().enumerate() {
+        if accounts[..i]
+            .iter()
+            .any(|other| account.is_same_account(other))
+        {
+            return Err(ProgramError::Custom(ALIASED_ACCOUNTS_ERROR));
+        }
+    }
+
+    let guards = infos.map(|info| {
+        Some((
+            info.lamports.try_borrow_mut().ok()?,
+            info.data.try_borrow_mut().ok()?,
+        ))
+    });
+    if guards.iter().any(Option::is_none) {
+        return Err(ProgramError::AccountBorrowFailed);
+    }
+    let mut guards = guards.map(|guard| guard.unwrap_unchecked());
+
+    // The guards above keep everyone else out while these are alive.
+    // `solana_program`'s entrypoint leaves the duplicate marker in the
+    // borrow state, and ours may be mid-borrow, so start afresh.
+    let borrow_states = accounts
+        .each_ref()
+        .map(|account| account.replace_borrow_state(0));
+
+    let result = f(&accounts);
+
+    for ((account, state), (_, data)) in accounts
+        .iter()
+        .zip(borrow_states)
+        .zip(&mut guards)
+    {
+        account.replace_borrow_state(state);
+        if data.len() != account.data_len() {
+            **data = core::slice::from_raw_parts_mut(
+                data.as_mut_ptr(),
+                account.data_len(),
+            );
+        }
+    }
+    Ok(result)
+}
+
+#[test]
+fn test_with_account_infos() {
+    let (_a_buffer, a) = crate::entrypoint_nostd::test_account(5, 8);
+    let (_b_buffer, b) = crate::entrypoint_nostd::test_account(0, 8);
+
+    assert_eq!(
+        with_account_infos([&a, &a.clone()], |_| ()),
+        Err(ProgramError::Custom(ALIASED_ACCOUNTS_ERROR))
+    );
+
+    with_account_infos([&a, &b], |[a_info, b_info]| {
+        // Our own borrows are locked out meanwhile
+        assert!(a.try_borrow_data().is_err());
+        assert!(b.try_borrow_lamports().is_err());
+
+        **b_info
+            .try_borrow_mut_lamports()
+            .unwrap() += 5;
+        **a_info
+            .try_borrow_mut_lamports()
+            .unwrap() -= 5;
+        a_info.try_borrow_mut_data().unwrap()[0] = 1;
+        b_info.realloc(100, false).unwrap();
+    })
+    .unwrap();
+
+    assert_eq!(*a.try_borrow_lamports().unwrap(), 0);
+    assert_eq!(*b.try_borrow_lamports().unwrap(), 5);
+    assert_eq!(a.try_borrow_data().unwrap()[0], 1);
+    assert_eq!(b.data_len(), 100);
+
+    let key = a
+        .with_account_info(|info| *info.key)
+        .unwrap();
+    assert_eq!(&key, a.key());
+    assert!(a.can_borrow_data_mut());
+}
+
+#[test]
+fn test_with_no_std_account_infos() {
+    let (_a_buffer, a) = crate::entrypoint_nostd::test_account(5, 8);
+    let (_b_buffer, b) = crate::entrypoint_nostd::test_account(0, 8);
+
+    with_account_infos([&a, &b], |[a_info, b_info]| unsafe {
+        assert_eq!(
+            with_no_std_account_infos([a_info, a_info], |_| ()),
+            Err(ProgramError::Custom(ALIASED_ACCOUNTS_ERROR))
+        );
+        let a_data = a_info.try_borrow_data().unwrap();
+        assert_eq!(
+            with_no_std_account_infos([a_info], |_| ()),
+            Err(ProgramError::AccountBorrowFailed)
+        );
+        drop(a_data);
+
+        with_no_std_account_infos([a_info, b_info], |[a, b]| {
+            assert_eq!(a.key(), a_info.key);
+
+            // The AccountInfos are locked out meanwhile
+            assert!(a_info.try_borrow_lamports().is_err());
+            assert!(b_info.try_borrow_data().is_err());
+
+            *a.try_borrow_mut_lamports().unwrap() -= 5;
+            *b.try_borrow_mut_lamports().unwrap() += 5;
+            a.try_borrow_mut_data().unwrap()[0] = 2;
+            b.realloc(100, false).unwrap();
+        })
+        .unwrap();
+
+        assert_eq!(a_info.lamports(), 0);
+        assert_eq!(b_info.lamports(), 5);
+        assert_eq!(a_info.try_borrow_data().unwrap()[0], 2);
+        assert_eq!(b_info.data_len(), 100);
+    })
+    .unwrap();
+
+    // Back in the original borrow state
+    assert!(a.can_borrow_data_mut());
+    assert_eq!(*a.try_borrow_lamports().unwrap(), 0);
+    assert_eq!(b.data_len(), 100);
+}
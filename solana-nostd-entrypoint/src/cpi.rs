@@ -0,0 +1,716 @@
+//! Cross-program invocation via `sol_invoke_signed_c`.
+//!
+//! [`InstructionRef`], [`AccountMetaRef`] and [`AccountInfoRef`] have
+//! the same layouts as their raw `*C` counterparts, but borrow what
+//! they point to, so the compiler rejects inputs that would dangle by
+//! the time of the syscall. Likewise, [`SignerSeed`] and
+//! [`SignerSeeds`] spell out the layout the syscall expects for the
+//! seeds of signing pdas; see [`seeds!`](crate::seeds).
+
+use {
+    crate::{
+        AccountInfoC, AccountMetaC, DataRef, DataRefMut, InstructionC,
+        LamportsRef, LamportsRefMut, NoStdAccountInfo,
+    },
+    core::{marker::PhantomData, mem::MaybeUninit, ops::Deref},
+    solana_program_error::{ProgramError, ProgramResult},
+    solana_pubkey::Pubkey,
+};
+
+/// An [`AccountMetaC`] borrowing its pubkey.
+#[derive(Debug, Clone)]
+#[repr(transparent)]
+pub struct AccountMetaRef<'a> {
+    meta: AccountMetaC,
+    _pubkey: PhantomData<&'a Pubkey>,
+}
+
+impl<'a> AccountMetaRef<'a> {
+    #[inline(always)]
+    pub fn new(
+        pubkey: &'a Pubkey,
+        is_writable: bool,
+        is_signer: bool,
+    ) -> AccountMetaRef<'a> {
+        AccountMetaRef {
+            meta: AccountMetaC {
+                pubkey,
+                is_writable,
+                is_signer,
+            },
+            _pubkey: PhantomData,
+        }
+    }
+
+    /// Uses signer = false. for signers use `readonly_signer`
+    #[inline(always)]
+    pub fn readonly(pubkey: &'a Pubkey) -> AccountMetaRef<'a> {
+        AccountMetaRef::new(pubkey, false, false)
+    }
+
+    /// Uses signer = false. for signers use `writable_signer`
+    #[inline(always)]
+    pub fn writable(pubkey: &'a Pubkey) -> AccountMetaRef<'a> {
+        AccountMetaRef::new(pubkey, true, false)
+    }
+
+    #[inline(always)]
+    pub fn readonly_signer(pubkey: &'a Pubkey) -> AccountMetaRef<'a> {
+        AccountMetaRef::new(pubkey, false, true)
+    }
+
+    #[inline(always)]
+    pub fn writable_signer(pubkey: &'a Pubkey) -> AccountMetaRef<'a> {
+        AccountMetaRef::new(pubkey, true, true)
+    }
+}
+
+impl Deref for AccountMetaRef<'_> {
+    type Target = AccountMetaC;
+
+    fn deref(&self) -> &AccountMetaC {
+        &self.meta
+    }
+}
+
+/// An [`AccountInfoC`] borrowing the [`NoStdAccountInfo`] it was built
+/// from.
+#[derive(Clone)]
+#[repr(transparent)]
+pub struct AccountInfoRef<'a> {
+    info: AccountInfoC,
+    _account: PhantomData<&'a NoStdAccountInfo>,
+}
+
+impl<'a> AccountInfoRef<'a> {
+    #[inline(always)]
+    pub fn new(account: &'a NoStdAccountInfo) -> AccountInfoRef<'a> {
+        AccountInfoRef {
+            info: account.to_info_c(),
+            _account: PhantomData,
+        }
+    }
+
+    /// Views a slice of these as the raw infos `invoke` expects.
+    #[inline(always)]
+    pub fn as_info_c_slice<'b>(
+        infos: &'b [AccountInfoRef<'a>],
+    ) -> &'b [AccountInfoC] {
+        // SAFETY: `AccountInfoRef` is a transparent `AccountInfoC`
+        unsafe {
+            core::slice::from_raw_parts(
+                infos.as_ptr() as *const AccountInfoC,
+                infos.len(),
+            )
+        }
+    }
+}
+
+impl Deref for AccountInfoRef<'_> {
+    type Target = AccountInfoC;
+
+    fn deref(&self) -> &AccountInfoC {
+        &self.info
+    }
+}
+
+/// An [`InstructionC`] borrowing its program id, account metas and
+/// data.
+#[derive(Debug, Clone)]
+#[repr(transparent)]
+pub struct InstructionRef<'a> {
+    instruction: InstructionC,
+    _borrows:
+        PhantomData<(&'a Pubkey, &'a [AccountMetaRef<'a>], &'a [u8])>,
+}
+
+impl<'a> InstructionRef<'a> {
+    #[inline(always)]
+    pub fn new(
+        program_id: &'a Pubkey,
+        accounts: &'a [AccountMetaRef<'a>],
+        data: &'a [u8],
+    ) -> InstructionRef<'a> {
+        InstructionRef {
+            instruction: InstructionC {
+                program_id,
+                accounts: accounts.as_ptr() as *const AccountMetaC,
+                accounts_len: accounts.len() as u64,
+                data: data.as_ptr(),
+                data_len: data.len() as u64,
+            },
+            _borrows: PhantomData,
+        }
+    }
+}
+
+impl Deref for InstructionRef<'_> {
+    type Target = InstructionC;
+
+    fn deref(&self) -> &InstructionC {
+        &self.instruction
+    }
+}
+
+impl NoStdAccountInfo {
+    /// CPI utility function
+    #[inline(always)]
+    pub fn to_info_ref(&self) -> AccountInfoRef<'_> {
+        AccountInfoRef::new(self)
+    }
+
+    /// CPI utility function
+    #[inline(always)]
+    pub fn to_meta_ref(&self) -> AccountMetaRef<'_> {
+        AccountMetaRef::new(
+            self.key(),
+            self.is_writable(),
+            self.is_signer(),
+        )
+    }
+
+    /// CPI utility function.
+    ///
+    /// Intended for pdas that did not sign transaction but need to sign
+    /// for cpi.
+    #[inline(always)]
+    pub fn to_meta_ref_signer(&self) -> AccountMetaRef<'_> {
+        AccountMetaRef::new(self.key(), self.is_writable(), true)
+    }
+}
+
+/// A single seed of a signing pda, laid out as the runtime's
+/// `SolSignerSeedC`.
+#[derive(Clone, Copy)]
+#[repr(C)]
+pub struct SignerSeed<'a> {
+    addr: *const u8,
+    len: u64,
+    _seed: PhantomData<&'a [u8]>,
+}
+
+impl<'a> SignerSeed<'a> {
+    #[inline(always)]
+    pub const fn new(seed: &'a [u8]) -> SignerSeed<'a> {
+        SignerSeed {
+            addr: seed.as_ptr(),
+            len: seed.len() as u64,
+            _seed: PhantomData,
+        }
+    }
+
+    #[inline(always)]
+    pub fn as_bytes(&self) -> &'a [u8] {
+        // SAFETY: built from a slice that outlives 'a
+        unsafe {
+            core::slice::from_raw_parts(self.addr, self.len as usize)
+        }
+    }
+}
+
+impl<'a> From<&'a [u8]> for SignerSeed<'a> {
+    fn from(seed: &'a [u8]) -> SignerSeed<'a> {
+        SignerSeed::new(seed)
+    }
+}
+
+/// All seeds of a signing pda, including its bump seed, laid out as the
+/// runtime's `SolSignerSeedsC`.
+#[derive(Clone, Copy)]
+#[repr(C)]
+pub struct SignerSeeds<'a> {
+    addr: *const SignerSeed<'a>,
+    len: u64,
+    _seeds: PhantomData<&'a [SignerSeed<'a>]>,
+}
+
+impl<'a> SignerSeeds<'a> {
+    #[inline(always)]
+    pub const fn new(seeds: &'a [SignerSeed<'a>]) -> SignerSeeds<'a> {
+        SignerSeeds {
+            addr: seeds.as_ptr(),
+            len: seeds.len() as u64,
+            _seeds: PhantomData,
+        }
+    }
+
+    #[inline(always)]
+    pub fn as_slice(&self) -> &'a [SignerSeed<'a>] {
+        // SAFETY: built from a slice that outlives 'a
+        unsafe {
+            core::slice::from_raw_parts(self.addr, self.len as usize)
+        }
+    }
+}
+
+/// The seeds of every pda signing a cpi, as passed to
+/// [`invoke_signed`].
+pub type Signers<'a> = [SignerSeeds<'a>];
+
+/// Builds [`SignerSeeds`] on the stack from seed expressions that
+/// deref to `[u8]`.
+///
+/// The seeds live in temporaries, so use the macro within the
+/// invoking statement:
+///
+/// ```ignore
+/// invoke_signed(
+///     &instruction,
+///     &infos,
+///     &[seeds!(b"vault", user.key().as_ref(), &[bump])],
+/// )?;
+/// ```
+#[macro_export]
+macro_rules! seeds {
+    ($($seed:expr),* $(,)?) => {
+        $crate::cpi::SignerSeeds::new(&[
+            $($crate::cpi::SignerSeed::new($seed)),*
+        ])
+    };
+}
+
+/// Invokes `instruction` with the given account infos.
+///
+/// Off-chain there is no runtime to invoke, so this does nothing and
+/// returns `Ok(())`.
+#[inline]
+pub fn invoke(
+    instruction: &InstructionC,
+    infos: &[AccountInfoC],
+) -> ProgramResult {
+    invoke_signed(instruction, infos, &[])
+}
+
+/// Invokes `instruction` with the given account infos, signing for the
+/// program derived addresses of `signers`.
+///
+/// A nonzero return code from the runtime is mapped to the
+/// corresponding `ProgramError`. Off-chain there is no runtime to
+/// invoke, so this does nothing and returns `Ok(())`, except that with
+/// the `test-utils` feature the invocation is recorded for
+/// `take_invoke`.
+#[inline]
+pub fn invoke_signed(
+    instruction: &InstructionC,
+    infos: &[AccountInfoC],
+    signers: &Signers,
+) -> ProgramResult {
+    #[cfg(target_os = "solana")]
+    {
+        let result = unsafe {
+            crate::syscalls::sol_invoke_signed_c(
+                instruction as *const InstructionC as *const u8,
+                infos.as_ptr() as *const u8,
+                infos.len() as u64,
+                signers.as_ptr() as *const u8,
+                signers.len() as u64,
+            )
+        };
+        match result {
+            0 => Ok(()),
+            error => Err(error.into()),
+        }
+    }
+
+    #[cfg(not(target_os = "solana"))]
+    {
+        #[cfg(any(test, feature = "test-utils"))]
+        record_invoke(instruction, infos, signers);
+        core::hint::black_box((instruction, infos, signers));
+        Ok(())
+    }
+}
+
+/// An invocation as seen by the runtime, with everything it pointed to
+/// copied out, for host tests to inspect.
+#[cfg(all(
+    not(target_os = "solana"),
+    any(test, feature = "test-utils")
+))]
+#[derive(Clone, Debug, PartialEq)]
+pub struct RecordedInvoke {
+    pub program_id: Pubkey,
+    /// `(pubkey, is_writable, is_signer)` for each account meta.
+    pub accounts: std::vec::Vec<(Pubkey, bool, bool)>,
+    pub data: std::vec::Vec<u8>,
+    /// The keys of the account infos.
+    pub infos: std::vec::Vec<Pubkey>,
+    pub signers: std::vec::Vec<std::vec::Vec<std::vec::Vec<u8>>>,
+}
+
+#[cfg(all(
+    not(target_os = "solana"),
+    any(test, feature = "test-utils")
+))]
+std::thread_local! {
+    static LAST_INVOKE: core::cell::RefCell<Option<RecordedInvoke>> =
+        const { core::cell::RefCell::new(None) };
+}
+
+/// Private: records an invocation for [`take_invoke`].
+#[cfg(all(
+    not(target_os = "solana"),
+    any(test, feature = "test-utils")
+))]
+fn record_invoke(
+    instruction: &InstructionC,
+    infos: &[AccountInfoC],
+    signers: &Signers,
+) {
+    let metas = unsafe {
+        core::slice::from_raw_parts(
+            instruction.accounts,
+            instruction.accounts_len as usize,
+        )
+    };
+    let data = unsafe {
+        core::slice::from_raw_parts(
+            instruction.data,
+            instruction.data_len as usize,
+        )
+    };
+    let recorded = RecordedInvoke {
+        program_id: unsafe { *instruction.program_id },
+        accounts: metas
+            .iter()
+            .map(|meta| unsafe {
+                (*meta.pubkey, meta.is_writable, meta.is_signer)
+            })
+            .collect(),
+        data: data.to_vec(),
+        infos: infos
+            .iter()
+            .map(|info| unsafe { *info.key })
+            .collect(),
+        signers: signers
+            .iter()
+            .map(|seeds| {
+                seeds
+                    .as_slice()
+                    .iter()
+                    .map(|seed| seed.as_bytes().to_vec())
+                    .collect()
+            })
+            .collect(),
+    };
+    LAST_INVOKE.with(|last| *last.borrow_mut() = Some(recorded));
+}
+
+/// Takes the last invocation made on this thread, if any since the
+/// last call.
+#[cfg(all(
+    not(target_os = "solana"),
+    any(test, feature = "test-utils")
+))]
+pub fn take_invoke() -> Option<RecordedInvoke> {
+    LAST_INVOKE.with(|last| last.borrow_mut().take())
+}
+
+/// Like [`invoke`], but builds the account infos itself and checks
+/// `accounts`' borrow states first.
+///
+/// See [`invoke_signed_checked`].
+#[inline]
+pub fn invoke_checked<const N: usize>(
+    instruction: &InstructionC,
+    accounts: [&NoStdAccountInfo; N],
+) -> ProgramResult {
+    invoke_signed_checked(instruction, accounts, &[])
+}
+
+/// Like [`invoke_signed`], but builds the account infos itself and
+/// checks `accounts`' borrow states first, so the callee can't write
+/// through memory that live `Ref`s or `RefMut`s point to.
+///
+/// Accounts the instruction passes as writable must not be borrowed at
+/// all, and the rest must not be mutably borrowed; otherwise this fails
+/// with [`ProgramError::AccountBorrowFailed`] without invoking. The
+/// accounts stay borrowed accordingly for the duration of the syscall.
+///
+/// The callee may resize writable accounts. Since no data borrow
+/// survives the call, the next borrow (and
+/// [`NoStdAccountInfo::data_len`]) sees the new length; the realloc
+/// bound of the executing program is unaffected.
+pub fn invoke_signed_checked<const N: usize>(
+    instruction: &InstructionC,
+    accounts: [&NoStdAccountInfo; N],
+    signers: &Signers,
+) -> ProgramResult {
+    invoke_signed_checked_slice::<N>(instruction, &accounts, signers)
+}
+
+/// Like [`invoke_signed_checked`], for a number of accounts only known
+/// at runtime.
+///
+/// The account infos are built in a stack buffer of `MAX` entries, so
+/// this fails with [`ProgramError::InvalidArgument`] if there are more
+/// accounts than that.
+pub fn invoke_signed_checked_slice<const MAX: usize>(
+    instruction: &InstructionC,
+    accounts: &[&NoStdAccountInfo],
+    signers: &Signers,
+) -> ProgramResult {
+    if accounts.len() > MAX {
+        return Err(ProgramError::InvalidArgument);
+    }
+    let metas: &[AccountMetaC] = unsafe {
+        core::slice::from_raw_parts(
+            instruction.accounts,
+            instruction.accounts_len as usize,
+        )
+    };
+
+    // Check everything before borrowing anything
+    for (i, account) in accounts.iter().enumerate() {
+        if accounts[..i]
+            .iter()
+            .any(|other| account.is_same_account(other))
+        {
+            continue;
+        }
+        let can_borrow = if is_writable_in(account, metas) {
+            account.can_borrow_lamports_mut()
+                && account.can_borrow_data_mut()
+        } else {
+            account.can_borrow_lamports() && account.can_borrow_data()
+        };
+        if !can_borrow {
+            return Err(ProgramError::AccountBorrowFailed);
+        }
+    }
+
+    // Held until the syscall returns. Duplicates are borrowed once.
+    let _borrows: [CpiBorrow; MAX] = core::array::from_fn(|i| {
+        let Some(&account) = accounts.get(i) else {
+            return CpiBorrow::Skipped;
+        };
+        if accounts[..i]
+            .iter()
+            .any(|other| account.is_same_account(other))
+        {
+            return CpiBorrow::Skipped;
+        }
+        // The checks above passed, so these cannot fail
+        unsafe {
+            if is_writable_in(account, metas) {
+                CpiBorrow::Writable(
+                    account
+                        .try_borrow_mut_lamports()
+                        .unwrap_unchecked(),
+                    account
+                        .try_borrow_mut_data()
+                        .unwrap_unchecked(),
+                )
+            } else {
+                CpiBorrow::Readonly(
+                    account
+                        .try_borrow_lamports()
+                        .unwrap_unchecked(),
+                    account
+                        .try_borrow_data()
+                        .unwrap_unchecked(),
+                )
+            }
+        }
+    });
+
+    let infos: [MaybeUninit<AccountInfoC>; MAX] =
+        core::array::from_fn(|i| match accounts.get(i) {
+            Some(account) => MaybeUninit::new(account.to_info_c()),
+            None => MaybeUninit::uninit(),
+        });
+    // SAFETY: the first `accounts.len()` infos were just written
+    let infos = unsafe {
+        core::slice::from_raw_parts(
+            infos.as_ptr() as *const AccountInfoC,
+            accounts.len(),
+        )
+    };
+    invoke_signed(instruction, infos, signers)
+}
+
+/// Private: implements `invoke` for a CPI helper struct in terms of its
+/// `invoke_signed`.
+macro_rules! impl_invoke {
+    ($instruction:ident) => {
+        impl $instruction<'_> {
+            #[inline]
+            pub fn invoke(
+                &self,
+            ) -> solana_program_error::ProgramResult {
+                self.invoke_signed(&[])
+            }
+        }
+    };
+}
+pub(crate) use impl_invoke;
+
+/// Private: the borrows [`invoke_signed_checked_slice`] holds on an
+/// account during the syscall. They are only ever dropped.
+#[allow(dead_code)]
+enum CpiBorrow<'a> {
+    Writable(LamportsRefMut<'a>, DataRefMut<'a>),
+    Readonly(LamportsRef<'a>, DataRef<'a>),
+    Skipped,
+}
+
+/// Private: whether any of `metas` passes `account` as writable.
+#[inline(always)]
+fn is_writable_in(
+    account: &NoStdAccountInfo,
+    metas: &[AccountMetaC],
+) -> bool {
+    metas.iter().any(|meta| {
+        meta.is_writable && unsafe { *meta.pubkey == *account.key() }
+    })
+}
+
+#[test]
+fn test_invoke_checked() {
+    use crate::{entrypoint_nostd::TestAccount, BorrowState};
+
+    let (_a_buffer, a) = crate::entrypoint_nostd::test_account(5, 8);
+    let (_b_buffer, b) = TestAccount {
+        key: Pubkey::new_from_array([2; 32]),
+        lamports: 5,
+        data_len: 8,
+        ..Default::default()
+    }
+    .build();
+    let metas = [
+        AccountMetaC::writable(a.key()),
+        AccountMetaC::readonly(b.key()),
+    ];
+    let instruction = InstructionC {
+        program_id: a.owner(),
+        accounts: metas.as_ptr(),
+        accounts_len: metas.len() as u64,
+        data: [].as_ptr(),
+        data_len: 0,
+    };
+
+    // Shared borrows are fine for readonly accounts
+    let b_data = b.try_borrow_data().unwrap();
+    assert_eq!(
+        invoke_checked(&instruction, [&a, &b, &a.clone()]),
+        Ok(())
+    );
+    drop(b_data);
+    assert_eq!(
+        take_invoke().unwrap().infos,
+        [*a.key(), *b.key(), *a.key()]
+    );
+
+    // But not for writable ones
+    let a_data = a.try_borrow_data().unwrap();
+    assert_eq!(
+        invoke_checked(&instruction, [&a, &b]),
+        Err(ProgramError::AccountBorrowFailed)
+    );
+    drop(a_data);
+
+    // Nor are mutable borrows of readonly accounts
+    let b_lamports = b.try_borrow_mut_lamports().unwrap();
+    assert_eq!(
+        invoke_checked(&instruction, [&a, &b]),
+        Err(ProgramError::AccountBorrowFailed)
+    );
+    drop(b_lamports);
+    assert_eq!(take_invoke(), None);
+
+    // Nothing stays borrowed afterwards
+    for account in [&a, &b] {
+        assert_eq!(
+            account.lamports_borrow_state(),
+            BorrowState::Unborrowed
+        );
+        assert_eq!(
+            account.data_borrow_state(),
+            BorrowState::Unborrowed
+        );
+    }
+}
+
+#[test]
+fn test_instruction_ref() {
+    use core::mem::{align_of, size_of};
+
+    assert_eq!(size_of::<InstructionRef>(), size_of::<InstructionC>());
+    assert_eq!(
+        align_of::<InstructionRef>(),
+        align_of::<InstructionC>()
+    );
+    assert_eq!(size_of::<AccountMetaRef>(), size_of::<AccountMetaC>());
+    assert_eq!(size_of::<AccountInfoRef>(), size_of::<AccountInfoC>());
+
+    let (_buffer, account) =
+        crate::entrypoint_nostd::test_account(5, 8);
+    let metas = [account.to_meta_ref_signer()];
+    let data = [2, 0, 0, 0];
+    let instruction =
+        InstructionRef::new(account.owner(), &metas, &data);
+
+    assert_eq!(
+        instruction.program_id,
+        account.owner() as *const Pubkey
+    );
+    assert_eq!(instruction.accounts_len, 1);
+    let meta = unsafe { &*instruction.accounts };
+    assert_eq!(meta.pubkey, account.key() as *const Pubkey);
+    assert!(meta.is_writable && meta.is_signer);
+    assert_eq!(instruction.data, data.as_ptr());
+
+    let infos = [account.to_info_ref()];
+    let infos_c = AccountInfoRef::as_info_c_slice(&infos);
+    assert_eq!(infos_c[0].key, account.key() as *const Pubkey);
+    assert_eq!(infos_c[0].data_len, 8);
+    assert_eq!(invoke(&instruction, infos_c), Ok(()));
+    assert_eq!(
+        take_invoke(),
+        Some(RecordedInvoke {
+            program_id: *account.owner(),
+            accounts: std::vec![(*account.key(), true, true)],
+            data: data.to_vec(),
+            infos: std::vec![*account.key()],
+            signers: std::vec![],
+        })
+    );
+}
+
+#[test]
+fn test_seeds() {
+    use core::mem::size_of;
+
+    // Same layout as a slice of slices on the targets we run on
+    assert_eq!(size_of::<SignerSeed>(), size_of::<&[u8]>());
+    assert_eq!(size_of::<SignerSeeds>(), size_of::<&[&[u8]]>());
+
+    let key = Pubkey::new_from_array([3; 32]);
+    let bump = 254;
+    let check = |signers: &Signers| {
+        assert_eq!(signers.len(), 2);
+        let seeds = signers[0].as_slice();
+        assert_eq!(seeds.len(), 3);
+        assert_eq!(seeds[0].as_bytes(), b"vault");
+        assert_eq!(seeds[1].as_bytes(), key.as_ref());
+        assert_eq!(seeds[2].as_bytes(), &[bump]);
+        assert_eq!(signers[1].as_slice()[0].as_bytes(), b"authority");
+    };
+    check(&[
+        seeds!(b"vault", key.as_ref(), &[bump]),
+        seeds!(b"authority"),
+    ]);
+
+    // The runtime sees the same seeds
+    let instruction = InstructionRef::new(&key, &[], &[]);
+    invoke_signed(
+        &instruction,
+        &[],
+        &[seeds!(b"vault", key.as_ref(), &[bump])],
+    )
+    .unwrap();
+    assert_eq!(
+        take_invoke().unwrap().signers,
+        [[b"vault".as_slice(), key.as_ref(), &[bump]]]
+    );
+}
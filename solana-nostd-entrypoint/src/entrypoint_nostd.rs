@@ -55,6 +55,51 @@ macro_rules! entrypoint_nostd {
     };
 }
 
+#[macro_export]
+macro_rules! entrypoint_nostd_dup_indices {
+    ($process_instruction:ident, $accounts:literal) => {
+        /// # Safety:
+        /// solana entrypoint
+        #[no_mangle]
+        pub unsafe extern "C" fn entrypoint(input: *mut u8) -> u64 {
+            // Create arrays of uninitialized AccountInfos and indices.
+            const UNINIT_INFO: core::mem::MaybeUninit<
+                NoStdAccountInfo,
+            > = core::mem::MaybeUninit::uninit();
+            let mut accounts = [UNINIT_INFO; $accounts];
+            let mut original_indices =
+                [core::mem::MaybeUninit::<u8>::uninit(); $accounts];
+
+            let (program_id, num_accounts, instruction_data) = unsafe {
+                $crate::deserialize_nostd_dup_indices::<$accounts>(
+                    input,
+                    &mut accounts,
+                    &mut original_indices,
+                )
+            };
+
+            let account_infos = core::slice::from_raw_parts(
+                accounts.as_ptr() as *const NoStdAccountInfo,
+                num_accounts,
+            );
+            let original_indices = core::slice::from_raw_parts(
+                original_indices.as_ptr() as *const u8,
+                num_accounts,
+            );
+
+            match $process_instruction(
+                &program_id,
+                account_infos,
+                original_indices,
+                &instruction_data,
+            ) {
+                Ok(()) => 0,
+                Err(error) => error.into(),
+            }
+        }
+    };
+}
+
 #[macro_export]
 macro_rules! entrypoint_nostd_no_duplicates {
     ($process_instruction:ident, $accounts:literal) => {
@@ -170,6 +215,38 @@ macro_rules! entrypoint_nostd_no_duplicates_no_program {
 pub unsafe fn deserialize_nostd<'a, const MAX_ACCOUNTS: usize>(
     input: *mut u8,
     accounts: &mut [MaybeUninit<NoStdAccountInfo>],
+) -> (&'a Pubkey, usize, &'a [u8]) {
+    deserialize_with_indices::<MAX_ACCOUNTS>(input, accounts, None)
+}
+
+/// Like [`deserialize_nostd`], additionally recording in
+/// `original_indices` the position each account first appeared at
+/// (its own position, unless it is a duplicate).
+///
+/// # Safety
+/// solana entrypoint
+pub unsafe fn deserialize_nostd_dup_indices<
+    'a,
+    const MAX_ACCOUNTS: usize,
+>(
+    input: *mut u8,
+    accounts: &mut [MaybeUninit<NoStdAccountInfo>],
+    original_indices: &mut [MaybeUninit<u8>],
+) -> (&'a Pubkey, usize, &'a [u8]) {
+    deserialize_with_indices::<MAX_ACCOUNTS>(
+        input,
+        accounts,
+        Some(original_indices),
+    )
+}
+
+/// Private: [`deserialize_nostd`], recording original indices into
+/// `original_indices` if given.
+#[inline(always)]
+unsafe fn deserialize_with_indices<'a, const MAX_ACCOUNTS: usize>(
+    input: *mut u8,
+    accounts: &mut [MaybeUninit<NoStdAccountInfo>],
+    mut original_indices: Option<&mut [MaybeUninit<u8>]>,
 ) -> (&'a Pubkey, usize, &'a [u8]) {
     let mut offset: usize = 0;
 
@@ -185,28 +262,10 @@ pub unsafe fn deserialize_nostd<'a, const MAX_ACCOUNTS: usize>(
         for i in 0..processed {
             let dup_info = *(input.add(offset) as *const u8);
             if dup_info == NON_DUP_MARKER {
-                // MAGNETAR FIELDS: safety depends on alignment, size
-                // 1) we will always be 8 byte aligned due to
-                //    align_offset
-                // 2) solana vm serialization format is consistent so
-                //    size is ok
-                let account_info: *mut NoStdAccountInfoInner =
-                    input.add(offset) as *mut _;
-
-                offset += size_of::<NoStdAccountInfoInner>();
-                offset += (*account_info).data_len;
-                offset += MAX_PERMITTED_DATA_INCREASE;
-                offset += (offset as *const u8)
-                    .align_offset(BPF_ALIGN_OF_U128);
-                offset += size_of::<u64>(); // MAGNETAR FIELDS: ignore rent epoch
-
-                // MAGNETAR FIELDS: reset borrow state right before
-                // pushing
-                (*account_info).borrow_state = 0b_0000_0000;
-
-                accounts[i].write(NoStdAccountInfo {
-                    inner: account_info,
-                });
+                accounts[i].write(parse_account(input, &mut offset));
+                if let Some(indices) = &mut original_indices {
+                    indices[i].write(i as u8);
+                }
             } else {
                 offset += 8;
                 // Duplicate account, clone the original
@@ -215,6 +274,9 @@ pub unsafe fn deserialize_nostd<'a, const MAX_ACCOUNTS: usize>(
                         .assume_init_ref()
                         .clone(),
                 );
+                if let Some(indices) = &mut original_indices {
+                    indices[i].write(dup_info);
+                }
             }
         }
 
@@ -225,19 +287,7 @@ pub unsafe fn deserialize_nostd<'a, const MAX_ACCOUNTS: usize>(
         // extra CU consumption of having to check the array
         // bounds at each iteration.
         for _ in processed..num_accounts {
-            if *(input.add(offset) as *const u8) == NON_DUP_MARKER {
-                let account_info: *mut NoStdAccountInfoInner =
-                    input.add(offset) as *mut _;
-                offset += size_of::<NoStdAccountInfoInner>();
-                offset += (*account_info).data_len;
-                offset += MAX_PERMITTED_DATA_INCREASE;
-                offset += (offset as *const u8)
-                    .align_offset(BPF_ALIGN_OF_U128);
-                offset += size_of::<u64>(); // MAGNETAR FIELDS: ignore
-                                            // rent epoch
-            } else {
-                offset += 8;
-            }
+            skip_account(input, &mut offset);
         }
 
         processed
@@ -262,6 +312,56 @@ pub unsafe fn deserialize_nostd<'a, const MAX_ACCOUNTS: usize>(
     (program_id, processed, instruction_data)
 }
 
+/// Private: parses the non-duplicate account at `offset`, advancing
+/// `offset` past it.
+#[inline(always)]
+unsafe fn parse_account(
+    input: *mut u8,
+    offset: &mut usize,
+) -> NoStdAccountInfo {
+    // MAGNETAR FIELDS: safety depends on alignment, size
+    // 1) we will always be 8 byte aligned due to align_offset
+    // 2) solana vm serialization format is consistent so size is ok
+    let account_info: *mut NoStdAccountInfoInner =
+        input.add(*offset) as *mut _;
+
+    *offset += size_of::<NoStdAccountInfoInner>();
+    *offset += (*account_info).data_len;
+    *offset += MAX_PERMITTED_DATA_INCREASE;
+    *offset += (input.add(*offset) as *const u8)
+        .align_offset(BPF_ALIGN_OF_U128);
+    *offset += size_of::<u64>(); // MAGNETAR FIELDS: ignore rent epoch
+
+    // MAGNETAR FIELDS: reset borrow state right before pushing
+    (*account_info).borrow_state = 0b_0000_0000;
+
+    // Record the length realloc limits are based on
+    (*account_info).original_data_len = (*account_info).data_len as u32;
+
+    NoStdAccountInfo {
+        inner: account_info,
+    }
+}
+
+/// Private: skips the account at `offset`, duplicate or not, without
+/// touching it.
+#[inline(always)]
+unsafe fn skip_account(input: *mut u8, offset: &mut usize) {
+    if *(input.add(*offset) as *const u8) == NON_DUP_MARKER {
+        let account_info: *mut NoStdAccountInfoInner =
+            input.add(*offset) as *mut _;
+        *offset += size_of::<NoStdAccountInfoInner>();
+        *offset += (*account_info).data_len;
+        *offset += MAX_PERMITTED_DATA_INCREASE;
+        *offset += (input.add(*offset) as *const u8)
+            .align_offset(BPF_ALIGN_OF_U128);
+        *offset += size_of::<u64>(); // MAGNETAR FIELDS: ignore rent
+                                     // epoch
+    } else {
+        *offset += 8;
+    }
+}
+
 /// # Safety
 /// solana entrypoint
 pub unsafe fn deserialize_nostd_no_dup<
@@ -287,28 +387,7 @@ pub unsafe fn deserialize_nostd_no_dup<
         for i in 0..processed {
             let dup_info = *(input.add(offset) as *const u8);
             if dup_info == NON_DUP_MARKER {
-                // MAGNETAR FIELDS: safety depends on alignment, size
-                // 1) we will always be 8 byte aligned due to
-                //    align_offset
-                // 2) solana vm serialization format is consistent so
-                //    size is ok
-                let account_info: *mut NoStdAccountInfoInner =
-                    input.add(offset) as *mut _;
-
-                offset += size_of::<NoStdAccountInfoInner>();
-                offset += (*account_info).data_len;
-                offset += MAX_PERMITTED_DATA_INCREASE;
-                offset += (offset as *const u8)
-                    .align_offset(BPF_ALIGN_OF_U128);
-                offset += size_of::<u64>(); // MAGNETAR FIELDS: ignore rent epoch
-
-                // MAGNETAR FIELDS: reset borrow state right before
-                // pushing
-                (*account_info).borrow_state = 0b_0000_0000;
-
-                accounts[i].write(NoStdAccountInfo {
-                    inner: account_info,
-                });
+                accounts[i].write(parse_account(input, &mut offset));
             } else {
                 return None;
             }
@@ -321,19 +400,7 @@ pub unsafe fn deserialize_nostd_no_dup<
         // extra CU consumption of having to check the array
         // bounds at each iteration.
         for _ in processed..num_accounts {
-            if *(input.add(offset) as *const u8) == NON_DUP_MARKER {
-                let account_info: *mut NoStdAccountInfoInner =
-                    input.add(offset) as *mut _;
-                offset += size_of::<NoStdAccountInfoInner>();
-                offset += (*account_info).data_len;
-                offset += MAX_PERMITTED_DATA_INCREASE;
-                offset += (offset as *const u8)
-                    .align_offset(BPF_ALIGN_OF_U128);
-                offset += size_of::<u64>(); // MAGNETAR FIELDS: ignore
-                                            // rent epoch
-            } else {
-                offset += 8;
-            }
+            skip_account(input, &mut offset);
         }
 
         processed
@@ -382,28 +449,7 @@ pub unsafe fn deserialize_nostd_no_program<
         for i in 0..processed {
             let dup_info = *(input.add(offset) as *const u8);
             if dup_info == NON_DUP_MARKER {
-                // MAGNETAR FIELDS: safety depends on alignment, size
-                // 1) we will always be 8 byte aligned due to
-                //    align_offset
-                // 2) solana vm serialization format is consistent so
-                //    size is ok
-                let account_info: *mut NoStdAccountInfoInner =
-                    input.add(offset) as *mut _;
-
-                offset += size_of::<NoStdAccountInfoInner>();
-                offset += (*account_info).data_len;
-                offset += MAX_PERMITTED_DATA_INCREASE;
-                offset += (offset as *const u8)
-                    .align_offset(BPF_ALIGN_OF_U128);
-                offset += size_of::<u64>(); // MAGNETAR FIELDS: ignore rent epoch
-
-                // MAGNETAR FIELDS: reset borrow state right before
-                // pushing
-                (*account_info).borrow_state = 0b_0000_0000;
-
-                accounts[i].write(NoStdAccountInfo {
-                    inner: account_info,
-                });
+                accounts[i].write(parse_account(input, &mut offset));
             } else {
                 offset += 8;
                 // Duplicate account, clone the original
@@ -422,19 +468,7 @@ pub unsafe fn deserialize_nostd_no_program<
         // extra CU consumption of having to check the array
         // bounds at each iteration.
         for _ in processed..num_accounts {
-            if *(input.add(offset) as *const u8) == NON_DUP_MARKER {
-                let account_info: *mut NoStdAccountInfoInner =
-                    input.add(offset) as *mut _;
-                offset += size_of::<NoStdAccountInfoInner>();
-                offset += (*account_info).data_len;
-                offset += MAX_PERMITTED_DATA_INCREASE;
-                offset += (offset as *const u8)
-                    .align_offset(BPF_ALIGN_OF_U128);
-                offset += size_of::<u64>(); // MAGNETAR FIELDS: ignore
-                                            // rent epoch
-            } else {
-                offset += 8;
-            }
+            skip_account(input, &mut offset);
         }
 
         processed
@@ -480,28 +514,7 @@ pub unsafe fn deserialize_nostd_no_dup_no_program<
         for i in 0..processed {
             let dup_info = *(input.add(offset) as *const u8);
             if dup_info == NON_DUP_MARKER {
-                // MAGNETAR FIELDS: safety depends on alignment, size
-                // 1) we will always be 8 byte aligned due to
-                //    align_offset
-                // 2) solana vm serialization format is consistent so
-                //    size is ok
-                let account_info: *mut NoStdAccountInfoInner =
-                    input.add(offset) as *mut _;
-
-                offset += size_of::<NoStdAccountInfoInner>();
-                offset += (*account_info).data_len;
-                offset += MAX_PERMITTED_DATA_INCREASE;
-                offset += (offset as *const u8)
-                    .align_offset(BPF_ALIGN_OF_U128);
-                offset += size_of::<u64>(); // MAGNETAR FIELDS: ignore rent epoch
-
-                // MAGNETAR FIELDS: reset borrow state right before
-                // pushing
-                (*account_info).borrow_state = 0b_0000_0000;
-
-                accounts[i].write(NoStdAccountInfo {
-                    inner: account_info,
-                });
+                accounts[i].write(parse_account(input, &mut offset));
             } else {
                 return None;
             }
@@ -514,19 +527,7 @@ pub unsafe fn deserialize_nostd_no_dup_no_program<
         // extra CU consumption of having to check the array
         // bounds at each iteration.
         for _ in processed..num_accounts {
-            if *(input.add(offset) as *const u8) == NON_DUP_MARKER {
-                let account_info: *mut NoStdAccountInfoInner =
-                    input.add(offset) as *mut _;
-                offset += size_of::<NoStdAccountInfoInner>();
-                offset += (*account_info).data_len;
-                offset += MAX_PERMITTED_DATA_INCREASE;
-                offset += (offset as *const u8)
-                    .align_offset(BPF_ALIGN_OF_U128);
-                offset += size_of::<u64>(); // MAGNETAR FIELDS: ignore
-                                            // rent epoch
-            } else {
-                offset += 8;
-            }
+            skip_account(input, &mut offset);
         }
 
         processed
@@ -547,6 +548,10 @@ pub unsafe fn deserialize_nostd_no_dup_no_program<
     Some((processed, instruction_data))
 }
 
+/// The data left behind by
+/// [`NoStdAccountInfo::close_to_with_discriminator`].
+pub const CLOSED_ACCOUNT_DISCRIMINATOR: [u8; 8] = [0xff; 8];
+
 #[derive(Clone, PartialEq, Eq)]
 #[repr(C)]
 pub struct NoStdAccountInfo {
@@ -578,7 +583,10 @@ pub struct NoStdAccountInfoInner {
     /// read-only)
     executable: u8,
 
-    realloc_byte_counter: i32,
+    /// The data length at program entry, against which the runtime
+    /// bounds any realloc. We reuse the padding bytes for this, as
+    /// `solana_program` does.
+    original_data_len: u32,
 
     /// Public key of the account
     key: Pubkey,
@@ -720,16 +728,67 @@ pub struct InstructionC {
     pub data_len: u64,
 }
 
-pub struct Ref<'a, T: ?Sized> {
+/// The borrow state of an account's lamports or data, as tracked by one
+/// 4-bit half of [`NoStdAccountInfoInner`]'s borrow state.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BorrowState {
+    /// No outstanding borrows.
+    Unborrowed,
+    /// This many outstanding immutable borrows (at most 7).
+    Shared(u8),
+    /// One outstanding mutable borrow, possibly split into several
+    /// parts via [`RefMut::map_split`].
+    Exclusive,
+}
+
+impl BorrowState {
+    /// Private: decodes a 4-bit state [1 bit mutable borrow flag | u3
+    /// immutable borrow count].
+    #[inline(always)]
+    const fn from_nibble(nibble: u8) -> BorrowState {
+        if nibble & 0b_1000 != 0 {
+            BorrowState::Exclusive
+        } else if nibble & 0b_0111 != 0 {
+            BorrowState::Shared(nibble & 0b_0111)
+        } else {
+            BorrowState::Unborrowed
+        }
+    }
+}
+
+/// Borrow kind of a [`Ref`] or [`RefMut`] guarding the lamport field.
+pub const LAMPORTS_BORROW: u8 = 0;
+/// Borrow kind of a [`Ref`] or [`RefMut`] guarding the data field.
+pub const DATA_BORROW: u8 = 1;
+
+/// An immutable borrow of an account's lamports.
+pub type LamportsRef<'a, T = u64> = Ref<'a, T, LAMPORTS_BORROW>;
+/// A mutable borrow of an account's lamports.
+pub type LamportsRefMut<'a, T = u64> = RefMut<'a, T, LAMPORTS_BORROW>;
+/// An immutable borrow of an account's data.
+pub type DataRef<'a, T = [u8]> = Ref<'a, T, DATA_BORROW>;
+/// A mutable borrow of an account's data.
+pub type DataRefMut<'a, T = [u8]> = RefMut<'a, T, DATA_BORROW>;
+
+/// An immutable borrow guard. `KIND` selects which half of the borrow
+/// state is released on drop, so dropping never branches.
+pub struct Ref<'a, T: ?Sized, const KIND: u8 = DATA_BORROW> {
     value: NonNull<T>,
     state: NonNull<u8>,
-    is_lamport: bool,
     marker: PhantomData<&'a T>,
 }
 
-impl<'a, T: ?Sized> Ref<'a, T> {
+impl<'a, T: ?Sized, const KIND: u8> Ref<'a, T, KIND> {
+    /// The increment this guard added to the borrow state
+    const SHARED: u8 = if KIND == LAMPORTS_BORROW { 1 << 4 } else { 1 };
+    /// The immutable borrow count bits in the borrow state
+    const COUNT: u8 = Self::SHARED * 0b_0111;
+
     #[inline]
-    pub fn map<U: ?Sized, F>(orig: Ref<'a, T>, f: F) -> Ref<'a, U>
+    pub fn map<U: ?Sized, F>(
+        orig: Ref<'a, T, KIND>,
+        f: F,
+    ) -> Ref<'a, U, KIND>
     where
         F: FnOnce(&T) -> &U,
     {
@@ -739,16 +798,15 @@ impl<'a, T: ?Sized> Ref<'a, T> {
         Ref {
             value: NonNull::from(f(&*orig)),
             state: orig.state,
-            is_lamport: orig.is_lamport,
             marker: PhantomData,
         }
     }
 
     #[inline]
     pub fn filter_map<U: ?Sized, F>(
-        orig: Ref<'a, T>,
+        orig: Ref<'a, T, KIND>,
         f: F,
-    ) -> Result<Ref<'a, U>, Self>
+    ) -> Result<Ref<'a, U, KIND>, Self>
     where
         F: FnOnce(&T) -> Option<&U>,
     {
@@ -759,35 +817,69 @@ impl<'a, T: ?Sized> Ref<'a, T> {
             Some(value) => Ok(Ref {
                 value: NonNull::from(value),
                 state: orig.state,
-                is_lamport: orig.is_lamport,
                 marker: PhantomData,
             }),
             None => Err(ManuallyDrop::into_inner(orig)),
         }
     }
+
+    /// Splits a borrow into two borrows of disjoint parts of the
+    /// original, e.g. the header and the body of an account's data.
+    ///
+    /// Both halves count as one more immutable borrow, so the original
+    /// borrow is only released once both are dropped. Fails, returning
+    /// the original, if the max of 7 immutable borrows is reached.
+    #[inline]
+    pub fn map_split<U: ?Sized, V: ?Sized, F>(
+        orig: Ref<'a, T, KIND>,
+        f: F,
+    ) -> Result<(Ref<'a, U, KIND>, Ref<'a, V, KIND>), Self>
+    where
+        F: FnOnce(&T) -> (&U, &V),
+    {
+        let mut state = orig.state;
+        if unsafe { *state.as_ref() } & Self::COUNT == Self::COUNT {
+            return Err(orig);
+        }
+
+        // Avoid decrementing the borrow flag on Drop, and count the
+        // second half instead.
+        let orig = ManuallyDrop::new(orig);
+        unsafe { *state.as_mut() += Self::SHARED };
+
+        let (first, second) = f(&*orig);
+        Ok((
+            Ref {
+                value: NonNull::from(first),
+                state,
+                marker: PhantomData,
+            },
+            Ref {
+                value: NonNull::from(second),
+                state,
+                marker: PhantomData,
+            },
+        ))
+    }
 }
 
-impl<T: ?Sized> core::ops::Deref for Ref<'_, T> {
+impl<T: ?Sized, const KIND: u8> core::ops::Deref for Ref<'_, T, KIND> {
     type Target = T;
     fn deref(&self) -> &Self::Target {
         unsafe { self.value.as_ref() }
     }
 }
 
-impl<T: ?Sized> Drop for Ref<'_, T> {
+impl<T: ?Sized, const KIND: u8> Drop for Ref<'_, T, KIND> {
     // We just need to decrement the immutable borrow count
-    // maybe super minor todo: we can save the is_lamport check by using
-    // a separate ref type
     fn drop(&mut self) {
-        if self.is_lamport {
-            unsafe { *self.state.as_mut() -= 1 << 4 };
-        } else {
-            unsafe { *self.state.as_mut() -= 1 };
-        }
+        unsafe { *self.state.as_mut() -= Self::SHARED };
     }
 }
 
-impl<T: ?Sized + core::fmt::Debug> core::fmt::Debug for Ref<'_, T> {
+impl<T: ?Sized + core::fmt::Debug, const KIND: u8> core::fmt::Debug
+    for Ref<'_, T, KIND>
+{
     fn fmt(
         &self,
         f: &mut core::fmt::Formatter<'_>,
@@ -795,18 +887,35 @@ impl<T: ?Sized + core::fmt::Debug> core::fmt::Debug for Ref<'_, T> {
         write!(f, "{:?}", &**self)
     }
 }
-pub struct RefMut<'a, T: ?Sized> {
+
+/// A mutable borrow guard. `KIND` selects which half of the borrow
+/// state is released on drop, so dropping never branches.
+pub struct RefMut<'a, T: ?Sized, const KIND: u8 = DATA_BORROW> {
     value: NonNull<T>,
     state: NonNull<u8>,
-    is_lamport: bool,
     // `NonNull` is covariant over `T`, so we need to reintroduce
     // invariance.
     marker: PhantomData<&'a mut T>,
 }
 
-impl<'a, T: ?Sized> RefMut<'a, T> {
+impl<'a, T: ?Sized, const KIND: u8> RefMut<'a, T, KIND> {
+    /// The flag this guard set in the borrow state
+    const EXCLUSIVE: u8 = if KIND == LAMPORTS_BORROW {
+        0b_1000_0000
+    } else {
+        0b_0000_1000
+    };
+    /// One immutable borrow. While mutably borrowed the immutable count
+    /// is unused, so [`RefMut::map_split`] counts extra halves there.
+    const UNIT: u8 = Self::EXCLUSIVE >> 3;
+    /// The borrow state bits belonging to this kind of borrow
+    const NIBBLE: u8 = Self::UNIT * 0b_1111;
+
     #[inline]
-    pub fn map<U: ?Sized, F>(orig: RefMut<'a, T>, f: F) -> RefMut<'a, U>
+    pub fn map<U: ?Sized, F>(
+        orig: RefMut<'a, T, KIND>,
+        f: F,
+    ) -> RefMut<'a, U, KIND>
     where
         F: FnOnce(&mut T) -> &mut U,
     {
@@ -816,16 +925,15 @@ impl<'a, T: ?Sized> RefMut<'a, T> {
         RefMut {
             value: NonNull::from(f(&mut *orig)),
             state: orig.state,
-            is_lamport: orig.is_lamport,
             marker: PhantomData,
         }
     }
 
     #[inline]
     pub fn filter_map<U: ?Sized, F>(
-        orig: RefMut<'a, T>,
+        orig: RefMut<'a, T, KIND>,
         f: F,
-    ) -> Result<RefMut<'a, U>, Self>
+    ) -> Result<RefMut<'a, U, KIND>, Self>
     where
         F: FnOnce(&mut T) -> Option<&mut U>,
     {
@@ -838,41 +946,114 @@ impl<'a, T: ?Sized> RefMut<'a, T> {
                 Ok(RefMut {
                     value,
                     state: orig.state,
-                    is_lamport: orig.is_lamport,
                     marker: PhantomData,
                 })
             }
             None => Err(ManuallyDrop::into_inner(orig)),
         }
     }
+
+    /// Splits a borrow into two borrows of disjoint parts of the
+    /// original, e.g. to mutate the header of an account's data while
+    /// iterating over its body.
+    ///
+    /// The mutable borrow is only released once both halves are
+    /// dropped. Fails, returning the original, if it has already been
+    /// split into 8 live parts.
+    #[inline]
+    pub fn map_split<U: ?Sized, V: ?Sized, F>(
+        orig: RefMut<'a, T, KIND>,
+        f: F,
+    ) -> Result<(RefMut<'a, U, KIND>, RefMut<'a, V, KIND>), Self>
+    where
+        F: FnOnce(&mut T) -> (&mut U, &mut V),
+    {
+        let mut state = orig.state;
+        if unsafe { *state.as_ref() } & Self::NIBBLE == Self::NIBBLE {
+            return Err(orig);
+        }
+
+        // Avoid unsetting the mutable borrow flag on Drop, and count
+        // the second half instead.
+        let mut orig = ManuallyDrop::new(orig);
+        unsafe { *state.as_mut() += Self::UNIT };
+
+        let (first, second) = f(&mut *orig);
+        Ok((
+            RefMut {
+                value: NonNull::from(first),
+                state,
+                marker: PhantomData,
+            },
+            RefMut {
+                value: NonNull::from(second),
+                state,
+                marker: PhantomData,
+            },
+        ))
+    }
+
+    /// Turns this mutable borrow into an immutable one, without
+    /// releasing the borrow in between.
+    ///
+    /// Fails, returning the original, if this is a half from
+    /// [`RefMut::map_split`] whose other half is still alive.
+    #[inline]
+    pub fn downgrade(
+        orig: RefMut<'a, T, KIND>,
+    ) -> Result<Ref<'a, T, KIND>, Self> {
+        let mut state = orig.state;
+        if unsafe { *state.as_ref() } & Self::NIBBLE != Self::EXCLUSIVE
+        {
+            return Err(orig);
+        }
+
+        // Swap the mutable borrow flag for a single immutable borrow
+        let orig = ManuallyDrop::new(orig);
+        unsafe { *state.as_mut() ^= Self::EXCLUSIVE | Self::UNIT };
+
+        Ok(Ref {
+            value: orig.value,
+            state,
+            marker: PhantomData,
+        })
+    }
 }
 
-impl<T: ?Sized> core::ops::Deref for RefMut<'_, T> {
+impl<T: ?Sized, const KIND: u8> core::ops::Deref
+    for RefMut<'_, T, KIND>
+{
     type Target = T;
     fn deref(&self) -> &Self::Target {
         unsafe { self.value.as_ref() }
     }
 }
-impl<T: ?Sized> core::ops::DerefMut for RefMut<'_, T> {
+impl<T: ?Sized, const KIND: u8> core::ops::DerefMut
+    for RefMut<'_, T, KIND>
+{
     fn deref_mut(&mut self) -> &mut <Self as core::ops::Deref>::Target {
         unsafe { self.value.as_mut() }
     }
 }
 
-impl<T: ?Sized> Drop for RefMut<'_, T> {
-    // We need to unset the mut borrow flag
-    // maybe super minor todo: we can save the is_lamport check by using
-    // a separate type
+impl<T: ?Sized, const KIND: u8> Drop for RefMut<'_, T, KIND> {
+    // We need to unset the mut borrow flag, unless other halves from
+    // map_split are still alive. Without branching: release one unit,
+    // and if that took the flag with it (i.e. this was the last half),
+    // clear the leftover count bits too.
     fn drop(&mut self) {
-        if self.is_lamport {
-            unsafe { *self.state.as_mut() &= 0b_0111_1111 };
-        } else {
-            unsafe { *self.state.as_mut() &= 0b_1111_0111 };
-        }
+        let state = unsafe { self.state.as_mut() };
+        let released = *state - Self::UNIT;
+        let still_borrowed =
+            (released & Self::EXCLUSIVE) / Self::EXCLUSIVE;
+        let clear = still_borrowed.wrapping_sub(1) & Self::NIBBLE;
+        *state = released & !clear;
     }
 }
 
-impl<T: ?Sized + core::fmt::Debug> core::fmt::Debug for RefMut<'_, T> {
+impl<T: ?Sized + core::fmt::Debug, const KIND: u8> core::fmt::Debug
+    for RefMut<'_, T, KIND>
+{
     fn fmt(
         &self,
         f: &mut core::fmt::Formatter<'_>,
@@ -901,9 +1082,8 @@ pub struct RcRefCellInner<'a, T> {
 }
 
 impl<'a, T> RcRefCellInner<'a, T> {
-    #[allow(unused)] // used for sol_invoke_signed_rust, which is commented out for
-                     // now...
-    fn new(value: T) -> RcRefCellInner<'a, T> {
+    #[cfg_attr(not(feature = "account-info-compat"), allow(unused))]
+    pub(crate) fn new(value: T) -> RcRefCellInner<'a, T> {
         RcRefCellInner {
             strong: 2,
             weak: 2,
@@ -920,9 +1100,8 @@ impl<'a, T> RcRefCellInner<'a, T> {
     /// used. There can be no safe abstraction that guarantees users
     /// do this because we cannot make Rc inherit the borrowed
     /// lifetime.
-    #[allow(unused)] // used for sol_invoke_signed_rust, which is commented out for
-                     // now...
-    unsafe fn as_rcrc(&self) -> Rc<RefCell<T>> {
+    #[cfg_attr(not(feature = "account-info-compat"), allow(unused))]
+    pub(crate) unsafe fn as_rcrc(&self) -> Rc<RefCell<T>> {
         // Rc::from_raw expects pointer to T
         unsafe { Rc::from_raw(&self.refcell as *const RefCell<T>) }
     }
@@ -970,36 +1149,13 @@ impl NoStdAccountInfo {
         }
     }
 
-    // These two functions can be used to cpi via
-    // sol_invoke_signed_rust, but it is very easy to mess this up.
-    // Please just use sol_invoke_signed_c.
-    //
-    // pub unsafe fn unchecked_info_prep<'a>(
-    //     &'a self,
-    // ) -> (RcRefCellInner<&'a mut u64>, RcRefCellInner<&'a mut [u8]>)
-    // {     let lamports_inner =
-    // RcRefCellInner::new(self.unchecked_borrow_mut_lamports());
-    //     let data_inner =
-    // RcRefCellInner::new(self.unchecked_borrow_mut_data());
-    //     (lamports_inner, data_inner)
-    // }
-    // pub unsafe fn info_with<'a>(
-    //     &'a self,
-    //     lamports_data: &'a (RcRefCellInner<&'a mut u64>,
-    // RcRefCellInner<&'a mut [u8]>),
-    // ) -> solana_program::account_info::AccountInfo<'a> {
-    //     let (lamports, data) = lamports_data;
-    //     solana_program::account_info::AccountInfo {
-    //         key: self.key(),
-    //         lamports: unsafe { lamports.as_rcrc() },
-    //         data: unsafe { data.as_rcrc() },
-    //         owner: self.owner(),
-    //         rent_epoch: u64::MAX,
-    //         is_signer: self.is_signer(),
-    //         is_writable: self.is_writable(),
-    //         executable: self.executable(),
-    //     }
-    // }
+    /// Whether `other` refers to the same serialized account, e.g.
+    /// because the account was passed to the instruction twice. This is
+    /// a pointer comparison, not a key comparison.
+    #[inline(always)]
+    pub fn is_same_account(&self, other: &NoStdAccountInfo) -> bool {
+        core::ptr::eq(self.inner, other.inner)
+    }
 
     #[inline(always)]
     pub fn key(&self) -> &Pubkey {
@@ -1026,6 +1182,55 @@ impl NoStdAccountInfo {
         unsafe { (*self.inner).data_len }
     }
 
+    /// The current borrow state of the lamport field, shared across all
+    /// duplicates of this account.
+    #[inline(always)]
+    pub fn lamports_borrow_state(&self) -> BorrowState {
+        BorrowState::from_nibble(
+            unsafe { (*self.inner).borrow_state } >> 4,
+        )
+    }
+
+    /// The current borrow state of the data field, shared across all
+    /// duplicates of this account.
+    #[inline(always)]
+    pub fn data_borrow_state(&self) -> BorrowState {
+        BorrowState::from_nibble(
+            unsafe { (*self.inner).borrow_state } & 0b_0000_1111,
+        )
+    }
+
+    /// Whether [`NoStdAccountInfo::try_borrow_lamports`] would succeed.
+    #[inline(always)]
+    pub fn can_borrow_lamports(&self) -> bool {
+        matches!(
+            self.lamports_borrow_state(),
+            BorrowState::Unborrowed | BorrowState::Shared(..=6)
+        )
+    }
+
+    /// Whether [`NoStdAccountInfo::try_borrow_mut_lamports`] would
+    /// succeed.
+    #[inline(always)]
+    pub fn can_borrow_lamports_mut(&self) -> bool {
+        self.lamports_borrow_state() == BorrowState::Unborrowed
+    }
+
+    /// Whether [`NoStdAccountInfo::try_borrow_data`] would succeed.
+    #[inline(always)]
+    pub fn can_borrow_data(&self) -> bool {
+        matches!(
+            self.data_borrow_state(),
+            BorrowState::Unborrowed | BorrowState::Shared(..=6)
+        )
+    }
+
+    /// Whether [`NoStdAccountInfo::try_borrow_mut_data`] would succeed.
+    #[inline(always)]
+    pub fn can_borrow_data_mut(&self) -> bool {
+        self.data_borrow_state() == BorrowState::Unborrowed
+    }
+
     /// # Safety
     /// This does not check or modify the 4-bit refcell. Useful when
     /// instruction has verified non-duplicate accounts.
@@ -1064,7 +1269,7 @@ impl NoStdAccountInfo {
     /// already exist.
     pub fn try_borrow_lamports(
         &self,
-    ) -> Result<Ref<u64>, ProgramError> {
+    ) -> Result<LamportsRef<'_>, ProgramError> {
         let borrow_state = unsafe { &mut (*self.inner).borrow_state };
 
         // Check if mutable borrow is already taken
@@ -1086,7 +1291,6 @@ impl NoStdAccountInfo {
             state: unsafe {
                 NonNull::new_unchecked(&mut (*self.inner).borrow_state)
             },
-            is_lamport: true,
             marker: PhantomData,
         })
     }
@@ -1095,7 +1299,7 @@ impl NoStdAccountInfo {
     /// if the field is already borrowed in any form.
     pub fn try_borrow_mut_lamports(
         &self,
-    ) -> Result<RefMut<u64>, ProgramError> {
+    ) -> Result<LamportsRefMut<'_>, ProgramError> {
         let borrow_state = unsafe { &mut (*self.inner).borrow_state };
 
         // Check if any borrow (mutable or immutable) is already taken
@@ -1115,7 +1319,6 @@ impl NoStdAccountInfo {
             state: unsafe {
                 NonNull::new_unchecked(&mut (*self.inner).borrow_state)
             },
-            is_lamport: true,
             marker: PhantomData,
         })
     }
@@ -1123,7 +1326,7 @@ impl NoStdAccountInfo {
     /// Tries to get a read only reference to the data field, failing if
     /// the field is already mutable borrowed or if 7 borrows
     /// already exist.
-    pub fn try_borrow_data(&self) -> Result<Ref<[u8]>, ProgramError> {
+    pub fn try_borrow_data(&self) -> Result<DataRef<'_>, ProgramError> {
         let borrow_state = unsafe { &mut (*self.inner).borrow_state };
 
         // Check if mutable data borrow is already taken (most
@@ -1152,7 +1355,6 @@ impl NoStdAccountInfo {
             state: unsafe {
                 NonNull::new_unchecked(&mut (*self.inner).borrow_state)
             },
-            is_lamport: false,
             marker: PhantomData,
         })
     }
@@ -1161,7 +1363,7 @@ impl NoStdAccountInfo {
     /// the field is already borrowed in any form.
     pub fn try_borrow_mut_data(
         &self,
-    ) -> Result<RefMut<[u8]>, ProgramError> {
+    ) -> Result<DataRefMut<'_>, ProgramError> {
         let borrow_state = unsafe { &mut (*self.inner).borrow_state };
 
         // Check if any borrow (mutable or immutable) is already taken
@@ -1178,7 +1380,7 @@ impl NoStdAccountInfo {
         // Return the mutable reference to data
         Ok(RefMut {
             value: unsafe {
-                NonNull::new_unchecked(core::slice::from_raw_parts_mut(
+                NonNull::from(core::slice::from_raw_parts_mut(
                     self.data_ptr(),
                     (*self.inner).data_len,
                 ))
@@ -1186,7 +1388,6 @@ impl NoStdAccountInfo {
             state: unsafe {
                 NonNull::new_unchecked(&mut (*self.inner).borrow_state)
             },
-            is_lamport: false,
             marker: PhantomData,
         })
     }
@@ -1194,9 +1395,9 @@ impl NoStdAccountInfo {
     /// Realloc the account's data and optionally zero-initialize the
     /// new memory.
     ///
-    /// Note:  Account data can be increased within a single call by up
+    /// Note: Account data can be increased within a single call by up
     /// to `solana_program::entrypoint::MAX_PERMITTED_DATA_INCREASE`
-    /// bytes.
+    /// bytes over its length at program entry.
     ///
     /// Note: Memory used to grow is already zero-initialized upon
     /// program entrypoint and re-zeroing it wastes compute units.
@@ -1221,75 +1422,189 @@ impl NoStdAccountInfo {
         let old_len = data.len();
 
         // Return early if length hasn't changed
-        match new_len.cmp(&old_len) {
-            // Nothing to do
-            core::cmp::Ordering::Equal => return Ok(()),
-
-            // No checks
-            // Old len fits in an i32, and new_len is smaller so new len
-            // fits in i32.
-            core::cmp::Ordering::Less => {
-                unsafe {
-                    (*self.inner).realloc_byte_counter -=
-                        (old_len - new_len) as i32;
-
-                    // Set new length in the serialized data
-                    (*self.inner).data_len = new_len;
-                };
-            }
+        if new_len == old_len {
+            return Ok(());
+        }
 
-            // Need to check that the diff fits in an i32 and that max
-            // realloc has not been reached
-            core::cmp::Ordering::Greater => {
-                unsafe {
-                    // Check diff
-                    let new_counter = (*self.inner)
-                        .realloc_byte_counter
-                        + TryInto::<i32>::try_into(new_len - old_len)
-                            .map_err(|_| ProgramError::InvalidRealloc)?;
-
-                    // Check to see if we've exceeded max realloc across
-                    // all invocations
-                    if new_counter > MAX_PERMITTED_DATA_INCREASE as i32
-                    {
-                        return Err(ProgramError::InvalidRealloc);
-                    }
-
-                    // Set new length and new counter in the serialized
-                    // data after all validation
-                    (*self.inner).data_len = new_len;
-                    (*self.inner).realloc_byte_counter = new_counter;
-
-                    // Zero init if specified
-                    if zero_init {
-                        let len_increase = new_len - old_len;
-                        let new_data_slice =
-                            core::slice::from_raw_parts_mut(
-                                data.as_mut_ptr().add(old_len),
-                                len_increase,
-                            );
-                        sol_memset(new_data_slice, 0, len_increase);
-                    }
-                }
+        // The runtime only allows growing up to the data length at
+        // program entry plus the max increase, whatever happened in
+        // between (including reallocs within cpis, which update the
+        // serialized data length but not the original length).
+        let original_len = unsafe { (*self.inner).original_data_len };
+        if new_len
+            > (original_len as usize)
+                .saturating_add(MAX_PERMITTED_DATA_INCREASE)
+        {
+            return Err(ProgramError::InvalidRealloc);
+        }
+
+        unsafe {
+            // Set new length in the serialized data after all
+            // validation
+            (*self.inner).data_len = new_len;
+
+            // Zero init if specified
+            if zero_init && new_len > old_len {
+                let len_increase = new_len - old_len;
+                let new_data_slice = core::slice::from_raw_parts_mut(
+                    data.as_mut_ptr().add(old_len),
+                    len_increase,
+                );
+                sol_memset(new_data_slice, 0, len_increase);
             }
         }
 
         Ok(())
     }
 
-    pub fn assign(&self, new_owner: &Pubkey) {
-        // Set the non-mut owner field
-        unsafe {
-            (*self.inner).owner = *new_owner;
+    /// Assigns this account to `new_owner`, first enforcing the same
+    /// rules the runtime checks after the instruction:
+    ///
+    /// - the account must be writable and not executable, else
+    ///   [`ProgramError::Immutable`]
+    /// - it must be owned by `program_id`, the executing program, else
+    ///   [`ProgramError::InvalidAccountOwner`]
+    /// - its data must be empty or zeroed, else
+    ///   [`ProgramError::InvalidAccountData`]
+    pub fn try_assign(
+        &self,
+        new_owner: &Pubkey,
+        program_id: &Pubkey,
+    ) -> Result<(), ProgramError> {
+        if !self.is_writable() || self.executable() {
+            return Err(ProgramError::Immutable);
+        }
+        if self.owner() != program_id {
+            return Err(ProgramError::InvalidAccountOwner);
+        }
+        if self
+            .try_borrow_data()?
+            .iter()
+            .any(|byte| *byte != 0)
+        {
+            return Err(ProgramError::InvalidAccountData);
         }
+
+        unsafe { self.assign_unchecked(new_owner) };
+        Ok(())
     }
 
-    #[deprecated(since = "0.6.3")]
-    pub fn reassign(&self, new_owner: &Pubkey) {
+    /// # Safety
+    /// This does not check that the runtime will accept the owner
+    /// change; see [`NoStdAccountInfo::try_assign`]. It must also not
+    /// be called while a reference from [`NoStdAccountInfo::owner`] is
+    /// alive.
+    pub unsafe fn assign_unchecked(&self, new_owner: &Pubkey) {
         // Set the non-mut owner field
+        (*self.inner).owner = *new_owner;
+    }
+
+    #[deprecated(
+        since = "0.6.3",
+        note = "use `try_assign` or `assign_unchecked`"
+    )]
+    pub fn assign(&self, new_owner: &Pubkey) {
+        unsafe { self.assign_unchecked(new_owner) }
+    }
+
+    #[deprecated(
+        since = "0.6.3",
+        note = "use `try_assign` or `assign_unchecked`"
+    )]
+    pub fn reassign(&self, new_owner: &Pubkey) {
+        unsafe { self.assign_unchecked(new_owner) }
+    }
+
+    /// Closes this account by moving all of its lamports to
+    /// `destination`, truncating its data to zero bytes and assigning
+    /// it to the system program.
+    ///
+    /// All borrows and the lamport addition are validated before any
+    /// field is written, so on error the account is left untouched.
+    pub fn close_to(
+        &self,
+        destination: &NoStdAccountInfo,
+    ) -> Result<(), ProgramError> {
+        self.drain_lamports_to(destination, 0)?;
+
+        // The data is now empty, so the runtime accepts handing the
+        // account back to the system program
+        unsafe { self.assign_unchecked(&crate::system::ID) };
+
+        Ok(())
+    }
+
+    /// Closes this account like [`NoStdAccountInfo::close_to`], but
+    /// instead of handing it back to the system program, the data is
+    /// truncated to [`CLOSED_ACCOUNT_DISCRIMINATOR`] and the owner is
+    /// kept.
+    ///
+    /// Should the account be revived later in the same transaction
+    /// (e.g. by a transfer back into it), the program can still refuse
+    /// to load it via [`NoStdAccountInfo::expect_not_closed`].
+    pub fn close_to_with_discriminator(
+        &self,
+        destination: &NoStdAccountInfo,
+    ) -> Result<(), ProgramError> {
+        self.drain_lamports_to(
+            destination,
+            CLOSED_ACCOUNT_DISCRIMINATOR.len(),
+        )?;
+
+        // The realloc above borrowed and released the data, and we hold
+        // no other borrows here.
         unsafe {
-            (*self.inner).owner = *new_owner;
+            self.unchecked_borrow_mut_data()
+                .copy_from_slice(&CLOSED_ACCOUNT_DISCRIMINATOR);
+        }
+
+        Ok(())
+    }
+
+    /// Whether this account was closed via
+    /// [`NoStdAccountInfo::close_to_with_discriminator`].
+    pub fn is_closed(&self) -> Result<bool, ProgramError> {
+        let data = self.try_borrow_data()?;
+        Ok(*data == CLOSED_ACCOUNT_DISCRIMINATOR)
+    }
+
+    /// Fails with [`ProgramError::InvalidAccountData`] if this account
+    /// was closed via
+    /// [`NoStdAccountInfo::close_to_with_discriminator`].
+    pub fn expect_not_closed(&self) -> Result<(), ProgramError> {
+        if self.is_closed()? {
+            return Err(ProgramError::InvalidAccountData);
         }
+        Ok(())
+    }
+
+    /// Private: shared by the close functions. Moves all lamports to
+    /// `destination` and reallocs the data to `new_len`, only writing
+    /// once every check has passed.
+    fn drain_lamports_to(
+        &self,
+        destination: &NoStdAccountInfo,
+        new_len: usize,
+    ) -> Result<(), ProgramError> {
+        // Closing into itself would leave the lamports in place
+        if self == destination {
+            return Err(ProgramError::InvalidArgument);
+        }
+
+        let mut lamports = self.try_borrow_mut_lamports()?;
+        let mut destination_lamports =
+            destination.try_borrow_mut_lamports()?;
+        let new_destination_lamports = destination_lamports
+            .checked_add(*lamports)
+            .ok_or(ProgramError::ArithmeticOverflow)?;
+
+        // This is the last fallible step
+        self.realloc(new_len, false)?;
+
+        *destination_lamports = new_destination_lamports;
+        *lamports = 0;
+
+        Ok(())
     }
 
     /// Private: gets the memory addr of the account data
@@ -1299,6 +1614,87 @@ impl NoStdAccountInfo {
                 .add(size_of::<NoStdAccountInfoInner>())
         }
     }
+
+    /// Private: recovers the account whose serialized header holds the
+    /// key `key` points to.
+    ///
+    /// # Safety
+    /// `key` must point into a serialized account header.
+    #[cfg(feature = "account-info-compat")]
+    pub(crate) unsafe fn from_key_ptr(key: *const Pubkey) -> Self {
+        NoStdAccountInfo {
+            inner: key.byte_sub(core::mem::offset_of!(
+                NoStdAccountInfoInner,
+                key
+            )) as *mut NoStdAccountInfoInner,
+        }
+    }
+
+    /// Private: overwrites both 4-bit refcells, returning their old
+    /// state.
+    ///
+    /// # Safety
+    /// The caller must restore the old state before anyone relies on
+    /// it again.
+    #[cfg(feature = "account-info-compat")]
+    pub(crate) unsafe fn replace_borrow_state(&self, state: u8) -> u8 {
+        core::mem::replace(&mut (*self.inner).borrow_state, state)
+    }
+}
+
+/// Error returned by [`try_borrow_mut_data_many`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BorrowManyError {
+    /// The accounts at these two positions are the same account, e.g.
+    /// because it was passed to the instruction twice.
+    Aliased(usize, usize),
+    /// The data of the account at this position is already borrowed.
+    BorrowFailed(usize),
+}
+
+/// The [`ProgramError::Custom`] code of [`BorrowManyError::Aliased`],
+/// far above those programs usually number their own errors from.
+pub const ALIASED_ACCOUNTS_ERROR: u32 = 0x4142_0000;
+
+impl From<BorrowManyError> for ProgramError {
+    fn from(error: BorrowManyError) -> ProgramError {
+        match error {
+            BorrowManyError::Aliased(..) => {
+                ProgramError::Custom(ALIASED_ACCOUNTS_ERROR)
+            }
+            BorrowManyError::BorrowFailed(_) => {
+                ProgramError::AccountBorrowFailed
+            }
+        }
+    }
+}
+
+/// Mutably borrows the data of several accounts at once.
+///
+/// Every pair of accounts is first checked for aliasing (including
+/// duplicates of the same account), and every account for outstanding
+/// borrows. Only once all checks pass are the borrows taken, so on
+/// error no borrow state has changed.
+pub fn try_borrow_mut_data_many<'a, const N: usize>(
+    accounts: [&'a NoStdAccountInfo; N],
+) -> Result<[DataRefMut<'a>; N], BorrowManyError> {
+    for (i, account) in accounts.iter().enumerate() {
+        for (j, other) in accounts[..i].iter().enumerate() {
+            if account.is_same_account(other) {
+                return Err(BorrowManyError::Aliased(j, i));
+            }
+        }
+        if !account.can_borrow_data_mut() {
+            return Err(BorrowManyError::BorrowFailed(i));
+        }
+    }
+
+    // All accounts are distinct and unborrowed, so this cannot fail
+    Ok(accounts.map(|account| unsafe {
+        account
+            .try_borrow_mut_data()
+            .unwrap_unchecked()
+    }))
 }
 
 #[test]
@@ -1306,16 +1702,16 @@ fn test_ref() {
     let lamports_data: [u8; 8] =
         unsafe { core::mem::transmute([0u64; 1]) };
     let borrow_state = 1 << 4;
-    let byte_ref: Ref<[u8; 8]> = Ref {
+    let byte_ref: LamportsRef<[u8; 8]> = Ref {
         value: NonNull::from(&lamports_data),
         state: NonNull::from(&borrow_state),
-        is_lamport: true,
         marker: PhantomData,
     };
 
-    let lamports_ref: Ref<u64> = Ref::map(byte_ref, |b| unsafe {
-        core::mem::transmute::<&[u8; 8], &u64>(b)
-    });
+    let lamports_ref: LamportsRef<u64> =
+        Ref::map(byte_ref, |b| unsafe {
+            core::mem::transmute::<&[u8; 8], &u64>(b)
+        });
     assert_eq!(borrow_state, 1 << 4);
     assert_eq!(*lamports_ref, 0_u64);
 
@@ -1347,16 +1743,16 @@ fn test_ref_mut() {
     let lamports_data: [u8; 8] =
         unsafe { core::mem::transmute([0u64; 1]) };
     let borrow_state = 1 << 4;
-    let byte_ref: RefMut<[u8; 8]> = RefMut {
+    let byte_ref: LamportsRefMut<[u8; 8]> = RefMut {
         value: NonNull::from(&lamports_data),
         state: NonNull::from(&borrow_state),
-        is_lamport: true,
         marker: PhantomData,
     };
 
-    let lamports_ref: RefMut<u64> = RefMut::map(byte_ref, |b| unsafe {
-        core::mem::transmute::<&mut [u8; 8], &mut u64>(b)
-    });
+    let lamports_ref: LamportsRefMut<u64> =
+        RefMut::map(byte_ref, |b| unsafe {
+            core::mem::transmute::<&mut [u8; 8], &mut u64>(b)
+        });
     assert_eq!(borrow_state, 1 << 4);
     assert_eq!(*lamports_ref, 0_u64);
 
@@ -1385,3 +1781,398 @@ fn test_ref_mut() {
     assert_eq!(borrow_state, 1 << 4);
     assert_eq!(*even_lamports_ref.unwrap(), 2_u64);
 }
+
+/// Test helper: the header of an account laid out by
+/// [`TestAccount::build`]. The default is a writable non-signer with
+/// the default key, owned by `[1; 32]`, without lamports or data.
+#[cfg(test)]
+#[derive(Clone, Copy)]
+pub(crate) struct TestAccount {
+    pub key: Pubkey,
+    pub owner: Pubkey,
+    pub is_signer: bool,
+    pub is_writable: bool,
+    pub lamports: u64,
+    pub data_len: usize,
+}
+
+#[cfg(test)]
+impl Default for TestAccount {
+    fn default() -> Self {
+        TestAccount {
+            key: Pubkey::default(),
+            owner: Pubkey::new_from_array([1; 32]),
+            is_signer: false,
+            is_writable: true,
+            lamports: 0,
+            data_len: 0,
+        }
+    }
+}
+
+#[cfg(test)]
+impl TestAccount {
+    /// Lays out a single non-duplicate account the way the runtime
+    /// serializes it, returning the backing buffer alongside the
+    /// account info pointing into it.
+    pub fn build(self) -> (alloc::vec::Vec<u64>, NoStdAccountInfo) {
+        let len = size_of::<NoStdAccountInfoInner>()
+            + self.data_len
+            + MAX_PERMITTED_DATA_INCREASE
+            + size_of::<u64>();
+        let mut buffer = alloc::vec![0_u64; len.div_ceil(8)];
+        let inner = buffer.as_mut_ptr() as *mut NoStdAccountInfoInner;
+        unsafe {
+            *inner = NoStdAccountInfoInner {
+                is_signer: self.is_signer as u8,
+                is_writable: self.is_writable as u8,
+                key: self.key,
+                owner: self.owner,
+                lamports: self.lamports,
+                data_len: self.data_len,
+                original_data_len: self.data_len as u32,
+                ..Default::default()
+            };
+        }
+        (buffer, NoStdAccountInfo { inner })
+    }
+}
+
+/// Test helper: builds a default [`TestAccount`] with `lamports` and
+/// `data_len` bytes of data.
+#[cfg(test)]
+pub(crate) fn test_account(
+    lamports: u64,
+    data_len: usize,
+) -> (alloc::vec::Vec<u64>, NoStdAccountInfo) {
+    TestAccount {
+        lamports,
+        data_len,
+        ..Default::default()
+    }
+    .build()
+}
+
+/// Test helper: builds `N` default [`TestAccount`]s with the distinct
+/// keys `[1; 32]`, `[2; 32]` and so on.
+#[cfg(test)]
+pub(crate) fn test_accounts<const N: usize>(
+) -> ([alloc::vec::Vec<u64>; N], [NoStdAccountInfo; N]) {
+    let built: [_; N] = core::array::from_fn(|i| {
+        TestAccount {
+            key: Pubkey::new_from_array([i as u8 + 1; 32]),
+            ..Default::default()
+        }
+        .build()
+    });
+    let accounts = core::array::from_fn(|i| built[i].1.clone());
+    (built.map(|(buffer, _)| buffer), accounts)
+}
+
+/// Test helper: serializes an instruction input the way the runtime
+/// does, with `Ok((lamports, data_len))` for each new account and
+/// `Err(index)` for a duplicate of the account at `index`.
+#[cfg(test)]
+pub(crate) fn test_input(
+    accounts: &[Result<(u64, usize), u8>],
+    program_id: &Pubkey,
+) -> alloc::vec::Vec<u64> {
+    let mut input = alloc::vec![accounts.len() as u64];
+    for account in accounts {
+        match account {
+            Ok((lamports, data_len)) => {
+                let (buffer, info) = test_account(*lamports, *data_len);
+                unsafe { (*info.inner).borrow_state = NON_DUP_MARKER };
+                input.extend(buffer);
+            }
+            Err(index) => input.push(*index as u64),
+        }
+    }
+    // No instruction data, then the program id
+    input.push(0);
+    input.extend(
+        program_id
+            .to_bytes()
+            .chunks(8)
+            .map(|chunk| u64::from_le_bytes(chunk.try_into().unwrap())),
+    );
+    input
+}
+
+#[test]
+fn test_close_to() {
+    let (_buffer, account) = test_account(100, 16);
+    let (_dest_buffer, destination) = test_account(5, 0);
+
+    // Closing into itself (e.g. a duplicate) is rejected untouched
+    assert_eq!(
+        account.close_to(&account.clone()),
+        Err(ProgramError::InvalidArgument)
+    );
+    assert_eq!(*account.try_borrow_lamports().unwrap(), 100);
+
+    // An outstanding data borrow fails the close before any write
+    let data = account.try_borrow_data().unwrap();
+    assert_eq!(
+        account.close_to(&destination),
+        Err(ProgramError::AccountBorrowFailed)
+    );
+    assert_eq!(
+        *destination
+            .try_borrow_lamports()
+            .unwrap(),
+        5
+    );
+    drop(data);
+
+    account.close_to(&destination).unwrap();
+    assert_eq!(*account.try_borrow_lamports().unwrap(), 0);
+    assert_eq!(
+        *destination
+            .try_borrow_lamports()
+            .unwrap(),
+        105
+    );
+    assert_eq!(account.data_len(), 0);
+    assert_eq!(account.owner(), &crate::system::ID);
+}
+
+#[test]
+fn test_close_to_with_discriminator() {
+    let (_buffer, account) = test_account(100, 16);
+    let (_dest_buffer, destination) = test_account(5, 0);
+
+    account.expect_not_closed().unwrap();
+    account
+        .close_to_with_discriminator(&destination)
+        .unwrap();
+    assert_eq!(
+        *destination
+            .try_borrow_lamports()
+            .unwrap(),
+        105
+    );
+    assert_eq!(account.owner(), &Pubkey::new_from_array([1; 32]));
+    assert_eq!(
+        account.expect_not_closed(),
+        Err(ProgramError::InvalidAccountData)
+    );
+}
+
+#[test]
+fn test_borrow_state() {
+    let (_buffer, account) = test_account(0, 8);
+    assert_eq!(account.data_borrow_state(), BorrowState::Unborrowed);
+    assert!(account.can_borrow_data_mut());
+
+    let refs: [_; 7] =
+        core::array::from_fn(|_| account.try_borrow_data().unwrap());
+    assert_eq!(account.data_borrow_state(), BorrowState::Shared(7));
+    assert!(!account.can_borrow_data());
+    assert!(account.try_borrow_data().is_err());
+    drop(refs);
+
+    let lamports = account
+        .try_borrow_mut_lamports()
+        .unwrap();
+    assert_eq!(account.lamports_borrow_state(), BorrowState::Exclusive);
+    assert!(!account.can_borrow_lamports());
+    assert!(account.can_borrow_data_mut());
+    drop(lamports);
+    assert_eq!(
+        account.lamports_borrow_state(),
+        BorrowState::Unborrowed
+    );
+}
+
+#[test]
+fn test_map_split() {
+    let (_buffer, account) = test_account(0, 16);
+
+    let data = account.try_borrow_mut_data().unwrap();
+    let (mut header, mut body) =
+        RefMut::map_split(data, |data| data.split_at_mut(8)).unwrap();
+    header[0] = 1;
+    body.fill(2);
+    drop(header);
+    assert_eq!(account.data_borrow_state(), BorrowState::Exclusive);
+    drop(body);
+    assert_eq!(account.data_borrow_state(), BorrowState::Unborrowed);
+
+    let data = account.try_borrow_data().unwrap();
+    let (header, body) =
+        Ref::map_split(data, |data| data.split_at(8)).unwrap();
+    assert_eq!(account.data_borrow_state(), BorrowState::Shared(2));
+    assert_eq!((header[0], &body[..]), (1, &[2; 8][..]));
+    drop((header, body));
+    assert_eq!(account.data_borrow_state(), BorrowState::Unborrowed);
+
+    // Only a borrow that is not split can be downgraded
+    let data = account.try_borrow_mut_data().unwrap();
+    let (header, body) =
+        RefMut::map_split(data, |data| data.split_at_mut(8)).unwrap();
+    let header = RefMut::downgrade(header).unwrap_err();
+    drop(body);
+    let header = RefMut::downgrade(header).unwrap();
+    assert_eq!(account.data_borrow_state(), BorrowState::Shared(1));
+    assert!(account.try_borrow_data().is_ok());
+    drop(header);
+    assert_eq!(account.data_borrow_state(), BorrowState::Unborrowed);
+    assert_eq!(
+        account.lamports_borrow_state(),
+        BorrowState::Unborrowed
+    );
+}
+
+#[test]
+fn test_borrow_mut_data_many() {
+    let (_a_buffer, a) = test_account(0, 8);
+    let (_b_buffer, b) = test_account(0, 8);
+    let a_dup = a.clone();
+
+    assert_eq!(
+        try_borrow_mut_data_many([&a, &b, &a_dup]).err(),
+        Some(BorrowManyError::Aliased(0, 2))
+    );
+
+    let b_data = b.try_borrow_data().unwrap();
+    assert_eq!(
+        try_borrow_mut_data_many([&a, &b]).err(),
+        Some(BorrowManyError::BorrowFailed(1))
+    );
+    assert_eq!(a.data_borrow_state(), BorrowState::Unborrowed);
+    drop(b_data);
+
+    let [mut a_data, b_data] =
+        try_borrow_mut_data_many([&a, &b]).unwrap();
+    a_data[0] = 1;
+    assert_eq!(b.data_borrow_state(), BorrowState::Exclusive);
+    drop((a_data, b_data));
+    assert_eq!(a.try_borrow_data().unwrap()[0], 1);
+
+    assert_eq!(
+        ProgramError::from(BorrowManyError::Aliased(0, 2)),
+        ProgramError::Custom(ALIASED_ACCOUNTS_ERROR)
+    );
+    assert_eq!(
+        ProgramError::from(BorrowManyError::BorrowFailed(1)),
+        ProgramError::AccountBorrowFailed
+    );
+}
+
+#[test]
+fn test_deserialize_dup_indices() {
+    // Two accounts with the first one repeated in between
+    let program_id = Pubkey::new_from_array([9; 32]);
+    let mut input =
+        test_input(&[Ok((1, 8)), Err(0), Ok((2, 0))], &program_id);
+
+    let mut accounts = [const { MaybeUninit::uninit() }; 4];
+    let mut original_indices = [MaybeUninit::uninit(); 4];
+    let (program_id, num_accounts, instruction_data) = unsafe {
+        deserialize_nostd_dup_indices::<4>(
+            input.as_mut_ptr() as *mut u8,
+            &mut accounts,
+            &mut original_indices,
+        )
+    };
+    assert_eq!(program_id, &Pubkey::new_from_array([9; 32]));
+    assert_eq!(num_accounts, 3);
+    assert!(instruction_data.is_empty());
+
+    let accounts = unsafe {
+        core::slice::from_raw_parts(
+            accounts.as_ptr() as *const NoStdAccountInfo,
+            num_accounts,
+        )
+    };
+    let original_indices = unsafe {
+        core::slice::from_raw_parts(
+            original_indices.as_ptr() as *const u8,
+            num_accounts,
+        )
+    };
+    assert_eq!(original_indices, [0, 0, 2]);
+    assert!(accounts[0].is_same_account(&accounts[1]));
+    assert!(!accounts[0].is_same_account(&accounts[2]));
+    assert_eq!(
+        *accounts[2]
+            .try_borrow_lamports()
+            .unwrap(),
+        2
+    );
+}
+
+#[test]
+fn test_realloc() {
+    let mut input = test_input(&[Ok((0, 100))], &Pubkey::default());
+    let mut accounts = [const { MaybeUninit::uninit() }; 1];
+    unsafe {
+        deserialize_nostd::<1>(
+            input.as_mut_ptr() as *mut u8,
+            &mut accounts,
+        )
+    };
+    let account = unsafe { accounts[0].assume_init_ref() };
+    let max_len = 100 + MAX_PERMITTED_DATA_INCREASE;
+
+    // Shrinking does not free up growth beyond the original length
+    account.realloc(0, false).unwrap();
+    assert_eq!(
+        account.realloc(max_len + 1, false),
+        Err(ProgramError::InvalidRealloc)
+    );
+    account.realloc(max_len, false).unwrap();
+    account.realloc(50, false).unwrap();
+    account.realloc(max_len, true).unwrap();
+    assert_eq!(account.data_len(), max_len);
+    assert!(account.try_borrow_data().unwrap()[50..]
+        .iter()
+        .all(|byte| *byte == 0));
+
+    // Growth done by a cpi callee is counted towards the same limit
+    account.realloc(100, false).unwrap();
+    unsafe { (*account.inner).data_len = 100 + 4_000 };
+    account.realloc(max_len, false).unwrap();
+    assert_eq!(
+        account.realloc(max_len + 1, false),
+        Err(ProgramError::InvalidRealloc)
+    );
+    assert_eq!(account.data_len(), max_len);
+}
+
+#[test]
+fn test_try_assign() {
+    let program_id = Pubkey::new_from_array([1; 32]);
+    let new_owner = Pubkey::new_from_array([2; 32]);
+    let (_buffer, account) = test_account(0, 8);
+
+    account.try_borrow_mut_data().unwrap()[0] = 1;
+    assert_eq!(
+        account.try_assign(&new_owner, &program_id),
+        Err(ProgramError::InvalidAccountData)
+    );
+    account.try_borrow_mut_data().unwrap()[0] = 0;
+    assert_eq!(
+        account.try_assign(&new_owner, &new_owner),
+        Err(ProgramError::InvalidAccountOwner)
+    );
+
+    account
+        .try_assign(&new_owner, &program_id)
+        .unwrap();
+    assert_eq!(account.owner(), &new_owner);
+
+    // Only the new owner could assign it again, and only if writable
+    let (_buffer, readonly) = TestAccount {
+        owner: new_owner,
+        is_writable: false,
+        data_len: 8,
+        ..Default::default()
+    }
+    .build();
+    assert_eq!(
+        readonly.try_assign(&program_id, &new_owner),
+        Err(ProgramError::Immutable)
+    );
+}
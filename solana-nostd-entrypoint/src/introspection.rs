@@ -0,0 +1,319 @@
+//! Introspection of the executing instruction's place in the
+//! transaction: its stack height, i.e. how deep in CPI it runs, and
+//! the instructions processed before it at the same height.
+
+use {
+    crate::AccountMetaC, core::mem::MaybeUninit,
+    solana_program_error::ProgramError, solana_pubkey::Pubkey,
+};
+
+/// The stack height of instructions invoked directly by the
+/// transaction. Each level of CPI adds one.
+pub const TRANSACTION_LEVEL_STACK_HEIGHT: usize = 1;
+
+/// Returns the stack height of the executing instruction:
+/// [`TRANSACTION_LEVEL_STACK_HEIGHT`] if it was invoked by the
+/// transaction, and one more for each level of CPI.
+///
+/// Off-chain every instruction is top-level.
+#[inline]
+pub fn get_stack_height() -> usize {
+    #[cfg(target_os = "solana")]
+    unsafe {
+        crate::syscalls::sol_get_stack_height() as usize
+    }
+
+    #[cfg(not(target_os = "solana"))]
+    TRANSACTION_LEVEL_STACK_HEIGHT
+}
+
+/// Error returned by [`require_top_level`] and [`require_cpi_depth`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StackHeightError {
+    /// The instruction was reached through CPI at this depth, but must
+    /// be invoked by the transaction.
+    NotTopLevel(usize),
+    /// The instruction was reached at this CPI depth, 0 being
+    /// top-level, but must be reached at least `min` deep.
+    TooShallow { depth: usize, min: usize },
+}
+
+/// The [`ProgramError::Custom`] code of
+/// [`StackHeightError::NotTopLevel`]. Stack height errors use codes
+/// far above those programs usually number their own errors from.
+pub const NOT_TOP_LEVEL_ERROR: u32 = 0x5348_0000;
+
+/// The [`ProgramError::Custom`] code of
+/// [`StackHeightError::TooShallow`].
+pub const TOO_SHALLOW_ERROR: u32 = 0x5348_0001;
+
+impl From<StackHeightError> for ProgramError {
+    fn from(error: StackHeightError) -> ProgramError {
+        match error {
+            StackHeightError::NotTopLevel(_) => {
+                ProgramError::Custom(NOT_TOP_LEVEL_ERROR)
+            }
+            StackHeightError::TooShallow { .. } => {
+                ProgramError::Custom(TOO_SHALLOW_ERROR)
+            }
+        }
+    }
+}
+
+/// Fails unless the executing instruction was invoked directly by the
+/// transaction, e.g. for instructions that must never run inside
+/// another program's CPI.
+#[inline]
+pub fn require_top_level() -> Result<(), StackHeightError> {
+    match get_stack_height()
+        .saturating_sub(TRANSACTION_LEVEL_STACK_HEIGHT)
+    {
+        0 => Ok(()),
+        depth => Err(StackHeightError::NotTopLevel(depth)),
+    }
+}
+
+/// Fails unless the executing instruction was reached through at least
+/// `min` levels of CPI. `require_cpi_depth(1)` rejects top-level
+/// invocations.
+#[inline]
+pub fn require_cpi_depth(min: usize) -> Result<(), StackHeightError> {
+    let depth = get_stack_height()
+        .saturating_sub(TRANSACTION_LEVEL_STACK_HEIGHT);
+    if depth < min {
+        return Err(StackHeightError::TooShallow { depth, min });
+    }
+    Ok(())
+}
+
+/// Private: the lengths exchanged with
+/// `sol_get_processed_sibling_instruction`, the capacity on the way in
+/// and the actual lengths on the way out.
+#[cfg(target_os = "solana")]
+#[repr(C)]
+#[derive(Default)]
+pub(crate) struct ProcessedSiblingInstruction {
+    pub data_len: u64,
+    pub accounts_len: u64,
+}
+
+/// Private: an account meta as the runtime writes it, with the key
+/// inline.
+#[repr(C)]
+#[derive(Clone, Copy, Default)]
+pub(crate) struct RawAccountMeta {
+    pubkey: Pubkey,
+    is_signer: bool,
+    is_writable: bool,
+}
+
+/// Caller-provided storage for a processed sibling instruction with up
+/// to `ACCOUNTS` accounts and `DATA` bytes of data.
+///
+/// Lives on the stack and can be reused for several lookups.
+// Off-chain nothing is ever read into it
+#[cfg_attr(not(target_os = "solana"), allow(dead_code))]
+pub struct SiblingInstructionBuffer<
+    const ACCOUNTS: usize,
+    const DATA: usize,
+> {
+    program_id: Pubkey,
+    raw_metas: [RawAccountMeta; ACCOUNTS],
+    metas: [MaybeUninit<AccountMetaC>; ACCOUNTS],
+    data: [u8; DATA],
+}
+
+impl<const ACCOUNTS: usize, const DATA: usize> Default
+    for SiblingInstructionBuffer<ACCOUNTS, DATA>
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<const ACCOUNTS: usize, const DATA: usize>
+    SiblingInstructionBuffer<ACCOUNTS, DATA>
+{
+    #[inline(always)]
+    pub fn new() -> Self {
+        SiblingInstructionBuffer {
+            program_id: Pubkey::default(),
+            raw_metas: [RawAccountMeta::default(); ACCOUNTS],
+            metas: [const { MaybeUninit::uninit() }; ACCOUNTS],
+            data: [0; DATA],
+        }
+    }
+
+    /// Private: views the first `accounts_len` accounts and `data_len`
+    /// bytes of data, which the runtime has written.
+    #[cfg_attr(not(target_os = "solana"), allow(dead_code))]
+    #[inline(always)]
+    fn view(
+        &mut self,
+        accounts_len: usize,
+        data_len: usize,
+    ) -> SiblingInstruction<'_> {
+        for (meta, raw) in self.metas[..accounts_len]
+            .iter_mut()
+            .zip(&self.raw_metas)
+        {
+            meta.write(AccountMetaC {
+                pubkey: &raw.pubkey,
+                is_writable: raw.is_writable,
+                is_signer: raw.is_signer,
+            });
+        }
+        SiblingInstruction {
+            program_id: &self.program_id,
+            // SAFETY: the first `accounts_len` metas were just written
+            accounts: unsafe {
+                core::slice::from_raw_parts(
+                    self.metas.as_ptr() as *const AccountMetaC,
+                    accounts_len,
+                )
+            },
+            data: &self.data[..data_len],
+        }
+    }
+}
+
+/// A processed sibling instruction, borrowed from the
+/// [`SiblingInstructionBuffer`] it was read into.
+#[derive(Debug)]
+pub struct SiblingInstruction<'a> {
+    program_id: &'a Pubkey,
+    accounts: &'a [AccountMetaC],
+    data: &'a [u8],
+}
+
+impl<'a> SiblingInstruction<'a> {
+    #[inline(always)]
+    pub fn program_id(&self) -> &'a Pubkey {
+        self.program_id
+    }
+
+    #[inline(always)]
+    pub fn accounts(&self) -> &'a [AccountMetaC] {
+        self.accounts
+    }
+
+    #[inline(always)]
+    pub fn data(&self) -> &'a [u8] {
+        self.data
+    }
+}
+
+/// Reads the instruction processed `index` instructions before the
+/// executing one at the same stack height into `buffer`, 0 being the
+/// most recent. Instructions invoked by those siblings don't count.
+///
+/// Returns `None` if there is no such instruction, which is always
+/// the case off-chain, and fails with
+/// [`ProgramError::InvalidArgument`] if it doesn't fit in `buffer`.
+pub fn get_processed_sibling_instruction<
+    const ACCOUNTS: usize,
+    const DATA: usize,
+>(
+    index: usize,
+    buffer: &mut SiblingInstructionBuffer<ACCOUNTS, DATA>,
+) -> Result<Option<SiblingInstruction<'_>>, ProgramError> {
+    #[cfg(target_os = "solana")]
+    {
+        use crate::syscalls::sol_get_processed_sibling_instruction;
+
+        // The runtime only copies the instruction out if the lengths
+        // passed in match it exactly, so ask for them first
+        let mut meta = ProcessedSiblingInstruction::default();
+        let found = unsafe {
+            sol_get_processed_sibling_instruction(
+                index as u64,
+                &mut meta,
+                &mut buffer.program_id,
+                buffer.data.as_mut_ptr(),
+                buffer.raw_metas.as_mut_ptr(),
+            )
+        };
+        if found == 0 {
+            return Ok(None);
+        }
+        let (accounts_len, data_len) =
+            (meta.accounts_len as usize, meta.data_len as usize);
+        if accounts_len > ACCOUNTS || data_len > DATA {
+            return Err(ProgramError::InvalidArgument);
+        }
+        unsafe {
+            sol_get_processed_sibling_instruction(
+                index as u64,
+                &mut meta,
+                &mut buffer.program_id,
+                buffer.data.as_mut_ptr(),
+                buffer.raw_metas.as_mut_ptr(),
+            )
+        };
+        Ok(Some(buffer.view(accounts_len, data_len)))
+    }
+
+    #[cfg(not(target_os = "solana"))]
+    {
+        core::hint::black_box((index, buffer));
+        Ok(None)
+    }
+}
+
+#[test]
+fn test_stack_height() {
+    assert_eq!(get_stack_height(), TRANSACTION_LEVEL_STACK_HEIGHT);
+    assert_eq!(require_top_level(), Ok(()));
+    assert_eq!(require_cpi_depth(0), Ok(()));
+    assert_eq!(
+        require_cpi_depth(1),
+        Err(StackHeightError::TooShallow { depth: 0, min: 1 })
+    );
+    assert_eq!(
+        ProgramError::from(StackHeightError::NotTopLevel(1)),
+        ProgramError::Custom(NOT_TOP_LEVEL_ERROR)
+    );
+    assert_eq!(
+        ProgramError::from(StackHeightError::TooShallow {
+            depth: 0,
+            min: 1
+        }),
+        ProgramError::Custom(TOO_SHALLOW_ERROR)
+    );
+}
+
+#[test]
+fn test_sibling_instruction() {
+    let mut buffer = SiblingInstructionBuffer::<4, 8>::new();
+    assert!(get_processed_sibling_instruction(0, &mut buffer)
+        .unwrap()
+        .is_none());
+
+    // As the runtime would leave it
+    buffer.program_id = Pubkey::new_from_array([9; 32]);
+    buffer.raw_metas[0] = RawAccountMeta {
+        pubkey: Pubkey::new_from_array([1; 32]),
+        is_signer: true,
+        is_writable: false,
+    };
+    buffer.raw_metas[1] = RawAccountMeta {
+        pubkey: Pubkey::new_from_array([2; 32]),
+        is_signer: false,
+        is_writable: true,
+    };
+    buffer.data[..3].copy_from_slice(&[1, 2, 3]);
+
+    let instruction = buffer.view(2, 3);
+    assert_eq!(
+        instruction.program_id(),
+        &Pubkey::new_from_array([9; 32])
+    );
+    assert_eq!(instruction.data(), &[1, 2, 3]);
+    let [a, b] = instruction.accounts() else {
+        panic!("expected two accounts");
+    };
+    assert_eq!(unsafe { *a.pubkey }, Pubkey::new_from_array([1; 32]));
+    assert!(a.is_signer && !a.is_writable);
+    assert_eq!(unsafe { *b.pubkey }, Pubkey::new_from_array([2; 32]));
+    assert!(!b.is_signer && b.is_writable);
+}
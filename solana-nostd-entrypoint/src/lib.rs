@@ -1,8 +1,32 @@
 #![no_std]
 #![doc = include_str!(concat!(env!("CARGO_MANIFEST_DIR"), "/README.md"))]
 
+#[cfg(any(
+    test,
+    all(feature = "test-utils", not(target_os = "solana"))
+))]
+extern crate std;
+
 pub mod entrypoint_nostd;
 pub use entrypoint_nostd::*;
+#[cfg(feature = "token")]
+pub mod ata;
+#[cfg(feature = "account-info-compat")]
+pub mod compat;
+pub mod cpi;
+pub mod introspection;
+pub mod pda;
+pub mod return_data;
+#[cfg(target_os = "solana")]
+mod syscalls;
+pub mod system;
+pub mod sysvar;
+#[cfg(feature = "token")]
+pub mod token;
+#[cfg(feature = "token")]
+pub mod token_2022;
+#[cfg(feature = "token")]
+pub mod transfer_hook;
 pub mod __private {
     pub use solana_msg::sol_log;
 }
@@ -0,0 +1,202 @@
+//! Program derived addresses, computed directly via the
+//! `sol_create_program_address` and `sol_try_find_program_address`
+//! syscalls without going through `solana_program`.
+
+use {
+    crate::{cpi::SignerSeed, NoStdAccountInfo},
+    solana_program_error::ProgramError,
+    solana_pubkey::{Pubkey, MAX_SEEDS, MAX_SEED_LEN},
+};
+
+/// Creates a program address from `seeds`, which must already include
+/// the bump seed.
+///
+/// Fails with [`ProgramError::MaxSeedLengthExceeded`] if there are too
+/// many seeds or any seed is too long, and with
+/// [`ProgramError::InvalidSeeds`] if the address lands on the curve.
+#[inline]
+pub fn create_program_address(
+    seeds: &[&[u8]],
+    program_id: &Pubkey,
+) -> Result<Pubkey, ProgramError> {
+    let (seeds, len) = seeds_c(seeds, &[])?;
+    create_program_address_c(&seeds[..len], program_id)
+}
+
+/// Finds a valid program address and its bump seed for `seeds`.
+///
+/// # Panics
+///
+/// Panics in the (astronomically unlikely) event that no bump seed
+/// yields an off-curve address, or if the seeds are invalid, matching
+/// `Pubkey::find_program_address`.
+#[inline]
+pub fn find_program_address(
+    seeds: &[&[u8]],
+    program_id: &Pubkey,
+) -> (Pubkey, u8) {
+    try_find_program_address(seeds, program_id)
+        .expect("Unable to find a viable program address bump seed")
+}
+
+/// Finds a valid program address and its bump seed for `seeds`,
+/// returning `None` if the seeds are invalid or no bump seed works.
+#[inline]
+pub fn try_find_program_address(
+    seeds: &[&[u8]],
+    program_id: &Pubkey,
+) -> Option<(Pubkey, u8)> {
+    // The bump seed is appended by the runtime, so leave room for it
+    if seeds.len() >= MAX_SEEDS {
+        return None;
+    }
+    let (seeds, len) = seeds_c(seeds, &[]).ok()?;
+
+    #[cfg(target_os = "solana")]
+    {
+        let mut address = core::mem::MaybeUninit::<Pubkey>::uninit();
+        let mut bump = u8::MAX;
+        let result = unsafe {
+            crate::syscalls::sol_try_find_program_address(
+                seeds.as_ptr() as *const u8,
+                len as u64,
+                program_id as *const Pubkey as *const u8,
+                address.as_mut_ptr() as *mut u8,
+                &mut bump as *mut u8,
+            )
+        };
+        match result {
+            0 => Some((unsafe { address.assume_init() }, bump)),
+            _ => None,
+        }
+    }
+
+    #[cfg(not(target_os = "solana"))]
+    {
+        let mut slices: [&[u8]; MAX_SEEDS] = [&[]; MAX_SEEDS];
+        for (slice, seed) in slices.iter_mut().zip(&seeds[..len]) {
+            *slice = seed.as_bytes();
+        }
+        Pubkey::try_find_program_address(&slices[..len], program_id)
+    }
+}
+
+impl NoStdAccountInfo {
+    /// Checks that this account's key is the program address derived
+    /// from `seeds` and `bump`, failing with
+    /// [`ProgramError::InvalidSeeds`] otherwise.
+    ///
+    /// `seeds` should not include the bump seed.
+    pub fn expect_pda(
+        &self,
+        seeds: &[&[u8]],
+        bump: u8,
+        program_id: &Pubkey,
+    ) -> Result<(), ProgramError> {
+        let bump = [bump];
+        let (seeds, len) = seeds_c(seeds, &bump)?;
+        if create_program_address_c(&seeds[..len], program_id)?
+            != *self.key()
+        {
+            return Err(ProgramError::InvalidSeeds);
+        }
+        Ok(())
+    }
+}
+
+/// Private: lays out `seeds` followed by `extra` (the bump seed, if
+/// any) on the stack in the layout the syscalls expect, returning the
+/// array and the number of seeds written.
+#[inline(always)]
+fn seeds_c<'a>(
+    seeds: &[&'a [u8]],
+    extra: &'a [u8],
+) -> Result<([SignerSeed<'a>; MAX_SEEDS], usize), ProgramError> {
+    let len = seeds.len() + !extra.is_empty() as usize;
+    if len > MAX_SEEDS {
+        return Err(ProgramError::MaxSeedLengthExceeded);
+    }
+
+    let mut seeds_c = [SignerSeed::new(&[]); MAX_SEEDS];
+    for (seed_c, seed) in seeds_c
+        .iter_mut()
+        .zip(seeds.iter().chain(Some(&extra)))
+    {
+        if seed.len() > MAX_SEED_LEN {
+            return Err(ProgramError::MaxSeedLengthExceeded);
+        }
+        *seed_c = SignerSeed::new(seed);
+    }
+
+    Ok((seeds_c, len))
+}
+
+/// Private: creates a program address from seeds already laid out by
+/// [`seeds_c`].
+#[inline(always)]
+fn create_program_address_c(
+    seeds: &[SignerSeed],
+    program_id: &Pubkey,
+) -> Result<Pubkey, ProgramError> {
+    #[cfg(target_os = "solana")]
+    {
+        let mut address = core::mem::MaybeUninit::<Pubkey>::uninit();
+        let result = unsafe {
+            crate::syscalls::sol_create_program_address(
+                seeds.as_ptr() as *const u8,
+                seeds.len() as u64,
+                program_id as *const Pubkey as *const u8,
+                address.as_mut_ptr() as *mut u8,
+            )
+        };
+        match result {
+            0 => Ok(unsafe { address.assume_init() }),
+            _ => Err(ProgramError::InvalidSeeds),
+        }
+    }
+
+    #[cfg(not(target_os = "solana"))]
+    {
+        let mut slices: [&[u8]; MAX_SEEDS] = [&[]; MAX_SEEDS];
+        for (slice, seed) in slices.iter_mut().zip(seeds) {
+            *slice = seed.as_bytes();
+        }
+        Pubkey::create_program_address(
+            &slices[..seeds.len()],
+            program_id,
+        )
+        .map_err(|_| ProgramError::InvalidSeeds)
+    }
+}
+
+#[test]
+fn test_pda() {
+    let program_id = Pubkey::new_from_array([7; 32]);
+    let seeds: &[&[u8]] = &[b"vault", &[3; 32]];
+
+    let (address, bump) = find_program_address(seeds, &program_id);
+    assert_eq!(
+        (address, bump),
+        Pubkey::find_program_address(seeds, &program_id)
+    );
+    assert_eq!(
+        create_program_address(
+            &[seeds[0], seeds[1], &[bump]],
+            &program_id
+        ),
+        Ok(address)
+    );
+
+    // Too many or too long seeds are rejected up front
+    let empty: &[u8] = &[];
+    assert_eq!(
+        create_program_address(&[&[0; 33]], &program_id),
+        Err(ProgramError::MaxSeedLengthExceeded)
+    );
+    assert_eq!(
+        create_program_address(&[empty; MAX_SEEDS + 1], &program_id),
+        Err(ProgramError::MaxSeedLengthExceeded)
+    );
+    assert!(try_find_program_address(&[empty; MAX_SEEDS], &program_id)
+        .is_none());
+}
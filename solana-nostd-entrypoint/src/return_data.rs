@@ -0,0 +1,207 @@
+//! Return data, set by a program for its caller to read after a CPI,
+//! via the `sol_set_return_data` and `sol_get_return_data` syscalls.
+//!
+//! Reads go into caller-provided buffers rather than a `Vec`.
+
+use {solana_program_error::ProgramError, solana_pubkey::Pubkey};
+
+/// The most return data a program can set.
+pub const MAX_RETURN_DATA: usize = 1024;
+
+/// Sets the return data of the executing instruction, replacing any set
+/// before. The runtime rejects more than [`MAX_RETURN_DATA`] bytes.
+///
+/// Off-chain there is no runtime to hand the data to, so this does
+/// nothing.
+#[inline]
+pub fn set_return_data(data: &[u8]) {
+    #[cfg(target_os = "solana")]
+    unsafe {
+        crate::syscalls::sol_set_return_data(
+            data.as_ptr(),
+            data.len() as u64,
+        )
+    };
+
+    #[cfg(not(target_os = "solana"))]
+    core::hint::black_box(data);
+}
+
+/// Copies the return data of the last CPI into `buffer`, returning the
+/// program that set it and the full length of the data.
+///
+/// The length is that of all the return data, which can be larger than
+/// `N`: only the first `min(len, N)` bytes are copied. When nothing is
+/// copied, i.e. for `N == 0`, the runtime doesn't report the program
+/// either and the default pubkey is returned. Returns `None` if there
+/// is no return data, which is always the case off-chain.
+#[inline]
+pub fn get_return_data<const N: usize>(
+    buffer: &mut [u8; N],
+) -> Option<(Pubkey, usize)> {
+    get_return_data_raw(buffer.as_mut_ptr(), N)
+}
+
+/// Reads the return data of the last CPI as a `T`, checking that it
+/// was set by `program_id`.
+///
+/// Fails with [`ProgramError::InvalidArgument`] if there is no return
+/// data or it isn't exactly the size of a `T`, and with
+/// [`ProgramError::IncorrectProgramId`] if another program set it.
+#[inline]
+pub fn get_return_data_as<T: Pod>(
+    program_id: &Pubkey,
+) -> Result<T, ProgramError> {
+    let mut value = core::mem::MaybeUninit::<T>::zeroed();
+    let (setter, len) = get_return_data_raw(
+        value.as_mut_ptr() as *mut u8,
+        size_of::<T>(),
+    )
+    .ok_or(ProgramError::InvalidArgument)?;
+    if setter != *program_id {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+    if len != size_of::<T>() {
+        return Err(ProgramError::InvalidArgument);
+    }
+    // SAFETY: fully written, and any bit pattern is a valid `T`
+    Ok(unsafe { value.assume_init() })
+}
+
+/// Private: copies up to `len` bytes of return data to `data`.
+#[inline(always)]
+fn get_return_data_raw(
+    data: *mut u8,
+    len: usize,
+) -> Option<(Pubkey, usize)> {
+    // Only written if any data is copied
+    let mut program_id = Pubkey::default();
+
+    #[cfg(target_os = "solana")]
+    let size = unsafe {
+        crate::syscalls::sol_get_return_data(
+            data,
+            len as u64,
+            &mut program_id,
+        )
+    };
+
+    #[cfg(all(not(target_os = "solana"), test))]
+    let size =
+        unsafe { test_get_return_data(data, len, &mut program_id) };
+
+    #[cfg(not(any(target_os = "solana", test)))]
+    let size = {
+        core::hint::black_box((data, len, &mut program_id));
+        0
+    };
+
+    match size {
+        0 => None,
+        size => Some((program_id, size as usize)),
+    }
+}
+
+/// Plain old data: `Copy` types for which any bit pattern is valid, so
+/// return data can be read into them directly.
+///
+/// Return data is little endian, as are the targets programs run on.
+///
+/// # Safety
+/// Implementors must have no padding and no invalid bit patterns.
+pub unsafe trait Pod: Copy {}
+
+macro_rules! impl_pod {
+    ($($ty:ty),*) => {
+        $(unsafe impl Pod for $ty {})*
+    };
+}
+
+impl_pod!(u8, u16, u32, u64, u128, i8, i16, i32, i64, i128, Pubkey);
+
+unsafe impl<T: Pod, const N: usize> Pod for [T; N] {}
+
+#[cfg(all(not(target_os = "solana"), test))]
+std::thread_local! {
+    static TEST_RETURN_DATA: core::cell::RefCell<
+        (Pubkey, std::vec::Vec<u8>),
+    > = const {
+        core::cell::RefCell::new((
+            Pubkey::new_from_array([0; 32]),
+            std::vec::Vec::new(),
+        ))
+    };
+}
+
+/// Test helper: sets the return data [`test_get_return_data`] sees.
+#[cfg(all(not(target_os = "solana"), test))]
+fn set_test_return_data(program_id: &Pubkey, data: &[u8]) {
+    TEST_RETURN_DATA.with(|return_data| {
+        *return_data.borrow_mut() = (*program_id, data.to_vec())
+    });
+}
+
+/// Test helper: emulates `sol_get_return_data`, which copies at most
+/// `len` bytes, writes the program id only if it copied any, and
+/// returns the full length.
+#[cfg(all(not(target_os = "solana"), test))]
+unsafe fn test_get_return_data(
+    data: *mut u8,
+    len: usize,
+    program_id: *mut Pubkey,
+) -> u64 {
+    TEST_RETURN_DATA.with(|return_data| {
+        let (setter, return_data) = &*return_data.borrow();
+        let copied = len.min(return_data.len());
+        if copied != 0 {
+            core::ptr::copy_nonoverlapping(
+                return_data.as_ptr(),
+                data,
+                copied,
+            );
+            *program_id = *setter;
+        }
+        return_data.len() as u64
+    })
+}
+
+#[test]
+fn test_return_data() {
+    // Nothing is ever set off-chain
+    set_return_data(&[1, 2, 3]);
+    let mut buffer = [0; 8];
+    assert_eq!(get_return_data(&mut buffer), None);
+    assert_eq!(
+        get_return_data_as::<u64>(&Pubkey::default()),
+        Err(ProgramError::InvalidArgument)
+    );
+
+    let program_id = Pubkey::new_from_array([7; 32]);
+    set_test_return_data(&program_id, &[1, 2, 3, 4, 5]);
+
+    // Longer data is truncated, but its full length reported
+    let mut short = [0; 3];
+    assert_eq!(get_return_data(&mut short), Some((program_id, 5)));
+    assert_eq!(short, [1, 2, 3]);
+    let mut buffer = [0; 8];
+    assert_eq!(get_return_data(&mut buffer), Some((program_id, 5)));
+    assert_eq!(buffer, [1, 2, 3, 4, 5, 0, 0, 0]);
+
+    // Nothing copied, so no program either
+    assert_eq!(get_return_data(&mut []), Some((Pubkey::default(), 5)));
+    assert_eq!(
+        get_return_data_as::<[u8; 0]>(&program_id),
+        Err(ProgramError::IncorrectProgramId)
+    );
+
+    assert_eq!(
+        get_return_data_as::<u32>(&program_id),
+        Err(ProgramError::InvalidArgument)
+    );
+    set_test_return_data(&program_id, &0x0102_u64.to_le_bytes());
+    assert_eq!(get_return_data_as::<u64>(&program_id), Ok(0x0102));
+    assert_eq!(
+        get_return_data_as::<u64>(&Pubkey::default()),
+        Err(ProgramError::IncorrectProgramId)
+    );
+}
@@ -0,0 +1,796 @@
+//! Cross-program invocations of the system program.
+//!
+//! Each instruction is a struct of the accounts and arguments it takes.
+//! Its `invoke` and `invoke_signed` methods lay out the instruction
+//! data on the stack and go through [`invoke_signed_checked`], so none
+//! of the accounts may be borrowed at the time.
+
+use {
+    crate::{
+        cpi::{
+            impl_invoke, invoke_signed_checked, AccountMetaRef,
+            InstructionRef, Signers,
+        },
+        NoStdAccountInfo,
+    },
+    solana_program_error::{ProgramError, ProgramResult},
+    solana_pubkey::{Pubkey, MAX_SEED_LEN},
+};
+
+/// The system program id.
+pub const ID: Pubkey = Pubkey::new_from_array([0; 32]);
+
+/// The size of a nonce account's data.
+pub const NONCE_STATE_SIZE: usize = 80;
+
+/// Private: instruction data laid out on the stack the way the system
+/// program's bincode deserializer expects it.
+struct Data<const N: usize> {
+    buffer: [u8; N],
+    len: usize,
+}
+
+impl<const N: usize> Data<N> {
+    #[inline(always)]
+    fn new(discriminant: u32) -> Data<N> {
+        Data {
+            buffer: [0; N],
+            len: 0,
+        }
+        .push(&discriminant.to_le_bytes())
+    }
+
+    #[inline(always)]
+    fn push(mut self, bytes: &[u8]) -> Data<N> {
+        self.buffer[self.len..self.len + bytes.len()]
+            .copy_from_slice(bytes);
+        self.len += bytes.len();
+        self
+    }
+
+    #[inline(always)]
+    fn push_u64(self, value: u64) -> Data<N> {
+        self.push(&value.to_le_bytes())
+    }
+
+    /// Seeds are bincode strings, i.e. prefixed by their u64 length.
+    /// Only pushes of at most [`MAX_SEED_LEN`] bytes are reserved for.
+    #[inline(always)]
+    fn push_seed(self, seed: &str) -> Result<Data<N>, ProgramError> {
+        if seed.len() > MAX_SEED_LEN {
+            return Err(ProgramError::MaxSeedLengthExceeded);
+        }
+        Ok(self
+            .push_u64(seed.len() as u64)
+            .push(seed.as_bytes()))
+    }
+
+    #[inline(always)]
+    fn as_slice(&self) -> &[u8] {
+        &self.buffer[..self.len]
+    }
+}
+
+/// Private: invokes the system program.
+#[inline(always)]
+fn invoke_system<const N: usize>(
+    accounts: [&NoStdAccountInfo; N],
+    metas: &[AccountMetaRef],
+    data: &[u8],
+    signers: &Signers,
+) -> ProgramResult {
+    let instruction = InstructionRef::new(&ID, metas, data);
+    invoke_signed_checked(&instruction, accounts, signers)
+}
+
+/// Creates a new account owned by `owner`, funded by `from`.
+pub struct CreateAccount<'a> {
+    /// Funding account. Writable signer.
+    pub from: &'a NoStdAccountInfo,
+    /// New account. Writable signer.
+    pub to: &'a NoStdAccountInfo,
+    /// Lamports to transfer to the new account.
+    pub lamports: u64,
+    /// Bytes of data to allocate.
+    pub space: u64,
+    /// Program to assign the new account to.
+    pub owner: &'a Pubkey,
+}
+
+impl_invoke!(CreateAccount);
+
+impl CreateAccount<'_> {
+    #[inline(always)]
+    fn data(&self) -> Data<52> {
+        Data::new(0)
+            .push_u64(self.lamports)
+            .push_u64(self.space)
+            .push(self.owner.as_ref())
+    }
+
+    #[inline]
+    pub fn invoke_signed(&self, signers: &Signers) -> ProgramResult {
+        invoke_system(
+            [self.from, self.to],
+            &[
+                AccountMetaRef::writable_signer(self.from.key()),
+                AccountMetaRef::writable_signer(self.to.key()),
+            ],
+            self.data().as_slice(),
+            signers,
+        )
+    }
+}
+
+/// Assigns an account to `owner`.
+pub struct Assign<'a> {
+    /// Account to assign. Writable signer.
+    pub account: &'a NoStdAccountInfo,
+    /// Program to assign the account to.
+    pub owner: &'a Pubkey,
+}
+
+impl_invoke!(Assign);
+
+impl Assign<'_> {
+    #[inline(always)]
+    fn data(&self) -> Data<36> {
+        Data::new(1).push(self.owner.as_ref())
+    }
+
+    #[inline]
+    pub fn invoke_signed(&self, signers: &Signers) -> ProgramResult {
+        invoke_system(
+            [self.account],
+            &[AccountMetaRef::writable_signer(self.account.key())],
+            self.data().as_slice(),
+            signers,
+        )
+    }
+}
+
+/// Transfers lamports between system accounts.
+pub struct Transfer<'a> {
+    /// Funding account. Writable signer.
+    pub from: &'a NoStdAccountInfo,
+    /// Recipient account. Writable.
+    pub to: &'a NoStdAccountInfo,
+    /// Lamports to transfer.
+    pub lamports: u64,
+}
+
+impl_invoke!(Transfer);
+
+impl Transfer<'_> {
+    #[inline(always)]
+    fn data(&self) -> Data<12> {
+        Data::new(2).push_u64(self.lamports)
+    }
+
+    #[inline]
+    pub fn invoke_signed(&self, signers: &Signers) -> ProgramResult {
+        invoke_system(
+            [self.from, self.to],
+            &[
+                AccountMetaRef::writable_signer(self.from.key()),
+                AccountMetaRef::writable(self.to.key()),
+            ],
+            self.data().as_slice(),
+            signers,
+        )
+    }
+}
+
+/// Creates a new account at an address derived from `base` and `seed`.
+pub struct CreateAccountWithSeed<'a> {
+    /// Funding account. Writable signer.
+    pub from: &'a NoStdAccountInfo,
+    /// New account. Writable.
+    pub to: &'a NoStdAccountInfo,
+    /// Base account of the address. Signer, may be `from`.
+    pub base: &'a NoStdAccountInfo,
+    /// Seed of the address, at most [`MAX_SEED_LEN`] bytes.
+    pub seed: &'a str,
+    /// Lamports to transfer to the new account.
+    pub lamports: u64,
+    /// Bytes of data to allocate.
+    pub space: u64,
+    /// Program to assign the new account to.
+    pub owner: &'a Pubkey,
+}
+
+impl_invoke!(CreateAccountWithSeed);
+
+impl CreateAccountWithSeed<'_> {
+    #[inline(always)]
+    fn data(&self) -> Result<Data<124>, ProgramError> {
+        Ok(Data::new(3)
+            .push(self.base.key().as_ref())
+            .push_seed(self.seed)?
+            .push_u64(self.lamports)
+            .push_u64(self.space)
+            .push(self.owner.as_ref()))
+    }
+
+    #[inline]
+    pub fn invoke_signed(&self, signers: &Signers) -> ProgramResult {
+        invoke_system(
+            [self.from, self.to, self.base],
+            &[
+                AccountMetaRef::writable_signer(self.from.key()),
+                AccountMetaRef::writable(self.to.key()),
+                AccountMetaRef::readonly_signer(self.base.key()),
+            ],
+            self.data()?.as_slice(),
+            signers,
+        )
+    }
+}
+
+/// Advances the stored blockhash of a nonce account.
+pub struct AdvanceNonceAccount<'a> {
+    /// Nonce account. Writable.
+    pub nonce: &'a NoStdAccountInfo,
+    /// The recent blockhashes sysvar.
+    pub recent_blockhashes: &'a NoStdAccountInfo,
+    /// Nonce authority. Signer.
+    pub authority: &'a NoStdAccountInfo,
+}
+
+impl_invoke!(AdvanceNonceAccount);
+
+impl AdvanceNonceAccount<'_> {
+    #[inline(always)]
+    fn data(&self) -> Data<4> {
+        Data::new(4)
+    }
+
+    #[inline]
+    pub fn invoke_signed(&self, signers: &Signers) -> ProgramResult {
+        invoke_system(
+            [self.nonce, self.recent_blockhashes, self.authority],
+            &[
+                AccountMetaRef::writable(self.nonce.key()),
+                AccountMetaRef::readonly(self.recent_blockhashes.key()),
+                AccountMetaRef::readonly_signer(self.authority.key()),
+            ],
+            self.data().as_slice(),
+            signers,
+        )
+    }
+}
+
+/// Withdraws lamports from a nonce account.
+pub struct WithdrawNonceAccount<'a> {
+    /// Nonce account. Writable.
+    pub nonce: &'a NoStdAccountInfo,
+    /// Recipient account. Writable.
+    pub to: &'a NoStdAccountInfo,
+    /// The recent blockhashes sysvar.
+    pub recent_blockhashes: &'a NoStdAccountInfo,
+    /// The rent sysvar.
+    pub rent: &'a NoStdAccountInfo,
+    /// Nonce authority. Signer.
+    pub authority: &'a NoStdAccountInfo,
+    /// Lamports to withdraw.
+    pub lamports: u64,
+}
+
+impl_invoke!(WithdrawNonceAccount);
+
+impl WithdrawNonceAccount<'_> {
+    #[inline(always)]
+    fn data(&self) -> Data<12> {
+        Data::new(5).push_u64(self.lamports)
+    }
+
+    #[inline]
+    pub fn invoke_signed(&self, signers: &Signers) -> ProgramResult {
+        invoke_system(
+            [
+                self.nonce,
+                self.to,
+                self.recent_blockhashes,
+                self.rent,
+                self.authority,
+            ],
+            &[
+                AccountMetaRef::writable(self.nonce.key()),
+                AccountMetaRef::writable(self.to.key()),
+                AccountMetaRef::readonly(self.recent_blockhashes.key()),
+                AccountMetaRef::readonly(self.rent.key()),
+                AccountMetaRef::readonly_signer(self.authority.key()),
+            ],
+            self.data().as_slice(),
+            signers,
+        )
+    }
+}
+
+/// Initializes a nonce account holding [`NONCE_STATE_SIZE`] bytes of
+/// data.
+pub struct InitializeNonceAccount<'a> {
+    /// Nonce account. Writable.
+    pub nonce: &'a NoStdAccountInfo,
+    /// The recent blockhashes sysvar.
+    pub recent_blockhashes: &'a NoStdAccountInfo,
+    /// The rent sysvar.
+    pub rent: &'a NoStdAccountInfo,
+    /// Nonce authority to set.
+    pub authority: &'a Pubkey,
+}
+
+impl_invoke!(InitializeNonceAccount);
+
+impl InitializeNonceAccount<'_> {
+    #[inline(always)]
+    fn data(&self) -> Data<36> {
+        Data::new(6).push(self.authority.as_ref())
+    }
+
+    #[inline]
+    pub fn invoke_signed(&self, signers: &Signers) -> ProgramResult {
+        invoke_system(
+            [self.nonce, self.recent_blockhashes, self.rent],
+            &[
+                AccountMetaRef::writable(self.nonce.key()),
+                AccountMetaRef::readonly(self.recent_blockhashes.key()),
+                AccountMetaRef::readonly(self.rent.key()),
+            ],
+            self.data().as_slice(),
+            signers,
+        )
+    }
+}
+
+/// Changes the authority of a nonce account.
+pub struct AuthorizeNonceAccount<'a> {
+    /// Nonce account. Writable.
+    pub nonce: &'a NoStdAccountInfo,
+    /// Current nonce authority. Signer.
+    pub authority: &'a NoStdAccountInfo,
+    /// Nonce authority to set.
+    pub new_authority: &'a Pubkey,
+}
+
+impl_invoke!(AuthorizeNonceAccount);
+
+impl AuthorizeNonceAccount<'_> {
+    #[inline(always)]
+    fn data(&self) -> Data<36> {
+        Data::new(7).push(self.new_authority.as_ref())
+    }
+
+    #[inline]
+    pub fn invoke_signed(&self, signers: &Signers) -> ProgramResult {
+        invoke_system(
+            [self.nonce, self.authority],
+            &[
+                AccountMetaRef::writable(self.nonce.key()),
+                AccountMetaRef::readonly_signer(self.authority.key()),
+            ],
+            self.data().as_slice(),
+            signers,
+        )
+    }
+}
+
+/// Allocates data for an account.
+pub struct Allocate<'a> {
+    /// Account to allocate. Writable signer.
+    pub account: &'a NoStdAccountInfo,
+    /// Bytes of data to allocate.
+    pub space: u64,
+}
+
+impl_invoke!(Allocate);
+
+impl Allocate<'_> {
+    #[inline(always)]
+    fn data(&self) -> Data<12> {
+        Data::new(8).push_u64(self.space)
+    }
+
+    #[inline]
+    pub fn invoke_signed(&self, signers: &Signers) -> ProgramResult {
+        invoke_system(
+            [self.account],
+            &[AccountMetaRef::writable_signer(self.account.key())],
+            self.data().as_slice(),
+            signers,
+        )
+    }
+}
+
+/// Allocates data for an account at an address derived from `base` and
+/// `seed`, and assigns it to `owner`.
+pub struct AllocateWithSeed<'a> {
+    /// Account to allocate. Writable.
+    pub account: &'a NoStdAccountInfo,
+    /// Base account of the address. Signer.
+    pub base: &'a NoStdAccountInfo,
+    /// Seed of the address, at most [`MAX_SEED_LEN`] bytes.
+    pub seed: &'a str,
+    /// Bytes of data to allocate.
+    pub space: u64,
+    /// Program to assign the account to.
+    pub owner: &'a Pubkey,
+}
+
+impl_invoke!(AllocateWithSeed);
+
+impl AllocateWithSeed<'_> {
+    #[inline(always)]
+    fn data(&self) -> Result<Data<116>, ProgramError> {
+        Ok(Data::new(9)
+            .push(self.base.key().as_ref())
+            .push_seed(self.seed)?
+            .push_u64(self.space)
+            .push(self.owner.as_ref()))
+    }
+
+    #[inline]
+    pub fn invoke_signed(&self, signers: &Signers) -> ProgramResult {
+        invoke_system(
+            [self.account, self.base],
+            &[
+                AccountMetaRef::writable(self.account.key()),
+                AccountMetaRef::readonly_signer(self.base.key()),
+            ],
+            self.data()?.as_slice(),
+            signers,
+        )
+    }
+}
+
+/// Assigns an account at an address derived from `base` and `seed` to
+/// `owner`.
+pub struct AssignWithSeed<'a> {
+    /// Account to assign. Writable.
+    pub account: &'a NoStdAccountInfo,
+    /// Base account of the address. Signer.
+    pub base: &'a NoStdAccountInfo,
+    /// Seed of the address, at most [`MAX_SEED_LEN`] bytes.
+    pub seed: &'a str,
+    /// Program to assign the account to.
+    pub owner: &'a Pubkey,
+}
+
+impl_invoke!(AssignWithSeed);
+
+impl AssignWithSeed<'_> {
+    #[inline(always)]
+    fn data(&self) -> Result<Data<108>, ProgramError> {
+        Ok(Data::new(10)
+            .push(self.base.key().as_ref())
+            .push_seed(self.seed)?
+            .push(self.owner.as_ref()))
+    }
+
+    #[inline]
+    pub fn invoke_signed(&self, signers: &Signers) -> ProgramResult {
+        invoke_system(
+            [self.account, self.base],
+            &[
+                AccountMetaRef::writable(self.account.key()),
+                AccountMetaRef::readonly_signer(self.base.key()),
+            ],
+            self.data()?.as_slice(),
+            signers,
+        )
+    }
+}
+
+/// Transfers lamports from an account at an address derived from
+/// `base` and `seed`.
+pub struct TransferWithSeed<'a> {
+    /// Funding account. Writable.
+    pub from: &'a NoStdAccountInfo,
+    /// Base account of the funding address. Signer.
+    pub base: &'a NoStdAccountInfo,
+    /// Recipient account. Writable.
+    pub to: &'a NoStdAccountInfo,
+    /// Lamports to transfer.
+    pub lamports: u64,
+    /// Seed of the funding address, at most [`MAX_SEED_LEN`] bytes.
+    pub seed: &'a str,
+    /// Owner program the funding address is derived with.
+    pub owner: &'a Pubkey,
+}
+
+impl_invoke!(TransferWithSeed);
+
+impl TransferWithSeed<'_> {
+    #[inline(always)]
+    fn data(&self) -> Result<Data<84>, ProgramError> {
+        Ok(Data::new(11)
+            .push_u64(self.lamports)
+            .push_seed(self.seed)?
+            .push(self.owner.as_ref()))
+    }
+
+    #[inline]
+    pub fn invoke_signed(&self, signers: &Signers) -> ProgramResult {
+        invoke_system(
+            [self.from, self.base, self.to],
+            &[
+                AccountMetaRef::writable(self.from.key()),
+                AccountMetaRef::readonly_signer(self.base.key()),
+                AccountMetaRef::writable(self.to.key()),
+            ],
+            self.data()?.as_slice(),
+            signers,
+        )
+    }
+}
+
+/// Upgrades a legacy nonce account to the current version.
+pub struct UpgradeNonceAccount<'a> {
+    /// Nonce account. Writable.
+    pub nonce: &'a NoStdAccountInfo,
+}
+
+impl_invoke!(UpgradeNonceAccount);
+
+impl UpgradeNonceAccount<'_> {
+    #[inline(always)]
+    fn data(&self) -> Data<4> {
+        Data::new(12)
+    }
+
+    #[inline]
+    pub fn invoke_signed(&self, signers: &Signers) -> ProgramResult {
+        invoke_system(
+            [self.nonce],
+            &[AccountMetaRef::writable(self.nonce.key())],
+            self.data().as_slice(),
+            signers,
+        )
+    }
+}
+
+#[test]
+fn test_system_data() {
+    let (_buffer, account) =
+        crate::entrypoint_nostd::test_account(5, 0);
+    let owner = Pubkey::new_from_array([9; 32]);
+
+    let transfer = Transfer {
+        from: &account,
+        to: &account,
+        lamports: 100_000_000,
+    };
+    assert_eq!(
+        transfer.data().as_slice(),
+        [2, 0, 0, 0, 0, 0xe1, 0xf5, 0x05, 0, 0, 0, 0]
+    );
+
+    let create = CreateAccount {
+        from: &account,
+        to: &account,
+        lamports: 1,
+        space: 2,
+        owner: &owner,
+    };
+    let data = create.data();
+    assert_eq!(data.as_slice().len(), 52);
+    assert_eq!(
+        data.as_slice()[..20],
+        [0, 0, 0, 0, 1, 0, 0, 0, 0, 0, 0, 0, 2, 0, 0, 0, 0, 0, 0, 0]
+    );
+    assert_eq!(data.as_slice()[20..], [9; 32]);
+
+    let mut with_seed = CreateAccountWithSeed {
+        from: &account,
+        to: &account,
+        base: &account,
+        seed: "seed",
+        lamports: 1,
+        space: 2,
+        owner: &owner,
+    };
+    let data = with_seed.data().unwrap();
+    assert_eq!(data.as_slice().len(), 4 + 32 + 8 + 4 + 8 + 8 + 32);
+    assert_eq!(data.as_slice()[..4], [3, 0, 0, 0]);
+    assert_eq!(data.as_slice()[36..48], *b"\x04\0\0\0\0\0\0\0seed");
+
+    // The longest seed fits, anything longer is rejected
+    with_seed.seed =
+        core::str::from_utf8(&[b'a'; MAX_SEED_LEN]).unwrap();
+    assert_eq!(
+        with_seed
+            .data()
+            .unwrap()
+            .as_slice()
+            .len(),
+        124
+    );
+    with_seed.seed =
+        core::str::from_utf8(&[b'a'; MAX_SEED_LEN + 1]).unwrap();
+    assert_eq!(
+        with_seed.invoke().err(),
+        Some(ProgramError::MaxSeedLengthExceeded)
+    );
+    assert_eq!(crate::cpi::take_invoke(), None);
+
+    let (_buffers, [from, to]) =
+        crate::entrypoint_nostd::test_accounts();
+    let transfer = Transfer {
+        from: &from,
+        to: &to,
+        lamports: 100_000_000,
+    };
+    transfer.invoke().unwrap();
+    let invoked = crate::cpi::take_invoke().unwrap();
+    assert_eq!(invoked.program_id, ID);
+    assert_eq!(
+        invoked.accounts,
+        [(*from.key(), true, true), (*to.key(), true, false)]
+    );
+    assert_eq!(invoked.infos, [*from.key(), *to.key()]);
+    assert_eq!(invoked.data, transfer.data().as_slice());
+}
+
+#[test]
+fn test_system_encodings() {
+    use {crate::cpi::take_invoke, std::vec::Vec};
+
+    // Accounts keyed [1; 32], [2; 32] and [3; 32]
+    let (_buffers, [a, b, c]) =
+        crate::entrypoint_nostd::test_accounts();
+    let owner = Pubkey::new_from_array([9; 32]);
+    let data = |result: ProgramResult| -> Vec<u8> {
+        result.unwrap();
+        take_invoke().unwrap().data
+    };
+    // "seed" as a bincode string
+    let seed: &[u8] = b"\x04\0\0\0\0\0\0\0seed";
+
+    assert_eq!(
+        data(
+            Assign {
+                account: &a,
+                owner: &owner,
+            }
+            .invoke()
+        ),
+        [&[1, 0, 0, 0][..], &[9; 32]].concat()
+    );
+    assert_eq!(
+        data(
+            AdvanceNonceAccount {
+                nonce: &a,
+                recent_blockhashes: &b,
+                authority: &c,
+            }
+            .invoke()
+        ),
+        [4, 0, 0, 0]
+    );
+    assert_eq!(
+        data(
+            WithdrawNonceAccount {
+                nonce: &a,
+                to: &b,
+                recent_blockhashes: &c,
+                rent: &c,
+                authority: &a,
+                lamports: 0x0102,
+            }
+            .invoke()
+        ),
+        [5, 0, 0, 0, 2, 1, 0, 0, 0, 0, 0, 0]
+    );
+    assert_eq!(
+        data(
+            InitializeNonceAccount {
+                nonce: &a,
+                recent_blockhashes: &b,
+                rent: &c,
+                authority: &owner,
+            }
+            .invoke()
+        ),
+        [&[6, 0, 0, 0][..], &[9; 32]].concat()
+    );
+    assert_eq!(
+        data(
+            AuthorizeNonceAccount {
+                nonce: &a,
+                authority: &b,
+                new_authority: &owner,
+            }
+            .invoke()
+        ),
+        [&[7, 0, 0, 0][..], &[9; 32]].concat()
+    );
+    assert_eq!(
+        data(
+            Allocate {
+                account: &a,
+                space: 0x0102,
+            }
+            .invoke()
+        ),
+        [8, 0, 0, 0, 2, 1, 0, 0, 0, 0, 0, 0]
+    );
+    assert_eq!(
+        data(
+            AllocateWithSeed {
+                account: &a,
+                base: &b,
+                seed: "seed",
+                space: 0x0102,
+                owner: &owner,
+            }
+            .invoke()
+        ),
+        [
+            &[9, 0, 0, 0][..],
+            &[2; 32],
+            seed,
+            &[2, 1, 0, 0, 0, 0, 0, 0],
+            &[9; 32],
+        ]
+        .concat()
+    );
+    assert_eq!(
+        data(
+            AssignWithSeed {
+                account: &a,
+                base: &b,
+                seed: "seed",
+                owner: &owner,
+            }
+            .invoke()
+        ),
+        [&[10, 0, 0, 0][..], &[2; 32], seed, &[9; 32]].concat()
+    );
+    assert_eq!(
+        data(
+            TransferWithSeed {
+                from: &a,
+                base: &b,
+                to: &c,
+                lamports: 0x0102,
+                seed: "seed",
+                owner: &owner,
+            }
+            .invoke()
+        ),
+        [
+            &[11, 0, 0, 0][..],
+            &[2, 1, 0, 0, 0, 0, 0, 0],
+            seed,
+            &[9; 32],
+        ]
+        .concat()
+    );
+    assert_eq!(
+        data(
+            CreateAccountWithSeed {
+                from: &a,
+                to: &b,
+                base: &c,
+                seed: "seed",
+                lamports: 1,
+                space: 0x0102,
+                owner: &owner,
+            }
+            .invoke()
+        ),
+        [
+            &[3, 0, 0, 0][..],
+            &[3; 32],
+            seed,
+            &[1, 0, 0, 0, 0, 0, 0, 0],
+            &[2, 1, 0, 0, 0, 0, 0, 0],
+            &[9; 32],
+        ]
+        .concat()
+    );
+    assert_eq!(
+        data(UpgradeNonceAccount { nonce: &a }.invoke()),
+        [12, 0, 0, 0]
+    );
+}
@@ -0,0 +1,272 @@
+//! Sysvars read directly via their syscalls into stack values, without
+//! going through `solana_program::sysvar`.
+
+use {
+    crate::{system, NoStdAccountInfo},
+    solana_program_error::ProgramError,
+    solana_pubkey::Pubkey,
+};
+
+/// The address of the rent sysvar account, for instructions that still
+/// expect it to be passed in.
+pub const RENT_ID: Pubkey = solana_pubkey::pubkey!(
+    "SysvarRent111111111111111111111111111111111"
+);
+
+/// The address of the recent blockhashes sysvar account, which the
+/// system program's nonce instructions expect to be passed in.
+pub const RECENT_BLOCKHASHES_ID: Pubkey = solana_pubkey::pubkey!(
+    "SysvarRecentB1ockHashes11111111111111111111"
+);
+
+/// Account storage overhead for calculation of base rent, i.e. the
+/// number of bytes charged for on top of the account data.
+pub const ACCOUNT_STORAGE_OVERHEAD: u64 = 128;
+
+/// The rent sysvar, laid out exactly as `sol_get_rent_sysvar` writes
+/// it.
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[repr(C)]
+pub struct Rent {
+    /// Rental rate in lamports per byte-year.
+    pub lamports_per_byte_year: u64,
+
+    /// Number of years' worth of rent an account must hold to be rent
+    /// exempt.
+    pub exemption_threshold: f64,
+
+    /// Percentage of collected rent that is burned.
+    pub burn_percent: u8,
+}
+
+impl Default for Rent {
+    /// The mainnet values, for use off-chain and in tests.
+    fn default() -> Rent {
+        Rent {
+            lamports_per_byte_year: 3_480,
+            exemption_threshold: 2.0,
+            burn_percent: 50,
+        }
+    }
+}
+
+impl Rent {
+    /// Reads the rent sysvar via `sol_get_rent_sysvar`.
+    ///
+    /// Off-chain there is no sysvar to read, so this fails with
+    /// [`ProgramError::UnsupportedSysvar`].
+    #[inline]
+    pub fn get() -> Result<Rent, ProgramError> {
+        #[cfg(target_os = "solana")]
+        {
+            let mut rent = core::mem::MaybeUninit::<Rent>::uninit();
+            let result = unsafe {
+                crate::syscalls::sol_get_rent_sysvar(
+                    rent.as_mut_ptr() as *mut u8
+                )
+            };
+            match result {
+                0 => Ok(unsafe { rent.assume_init() }),
+                error => Err(ProgramError::from(error)),
+            }
+        }
+
+        #[cfg(not(target_os = "solana"))]
+        Err(ProgramError::UnsupportedSysvar)
+    }
+
+    /// The minimum balance for an account with `data_len` bytes of data
+    /// to be rent exempt.
+    #[inline]
+    pub fn minimum_balance(&self, data_len: usize) -> u64 {
+        let bytes = ACCOUNT_STORAGE_OVERHEAD + data_len as u64;
+        let rent = bytes * self.lamports_per_byte_year;
+
+        // Avoid soft-float arithmetic for the threshold every cluster
+        // actually uses
+        if self.exemption_threshold == 2.0 {
+            rent * 2
+        } else {
+            (rent as f64 * self.exemption_threshold) as u64
+        }
+    }
+}
+
+impl NoStdAccountInfo {
+    /// Whether this account holds at least the rent exempt minimum
+    /// balance for its current data length.
+    pub fn is_rent_exempt(
+        &self,
+        rent: &Rent,
+    ) -> Result<bool, ProgramError> {
+        let lamports = self.try_borrow_lamports()?;
+        Ok(*lamports >= rent.minimum_balance(self.data_len()))
+    }
+
+    /// Reallocs the account's data like [`NoStdAccountInfo::realloc`],
+    /// transferring just enough lamports from `payer` for the account
+    /// to stay rent exempt at its new length.
+    ///
+    /// The lamports move through a system program transfer, so `payer`
+    /// must be a writable, system-owned signer, as when creating
+    /// accounts. For payers owned by the executing program, see
+    /// [`NoStdAccountInfo::realloc_and_fund_from_owned`].
+    pub fn realloc_and_fund(
+        &self,
+        new_len: usize,
+        zero_init: bool,
+        payer: &NoStdAccountInfo,
+        rent: &Rent,
+    ) -> Result<(), ProgramError> {
+        if self == payer {
+            return Err(ProgramError::InvalidArgument);
+        }
+
+        let top_up = rent
+            .minimum_balance(new_len)
+            .saturating_sub(*self.try_borrow_lamports()?);
+        self.realloc(new_len, zero_init)?;
+        if top_up > 0 {
+            system::Transfer {
+                from: payer,
+                to: self,
+                lamports: top_up,
+            }
+            .invoke()?;
+        }
+
+        Ok(())
+    }
+
+    /// Like [`NoStdAccountInfo::realloc_and_fund`], but debits `payer`
+    /// directly instead of invoking the system program.
+    ///
+    /// `payer` must be writable and owned by the executing program
+    /// (e.g. a vault pda); the runtime rejects the transaction
+    /// otherwise. Nothing is written unless every check passes.
+    pub fn realloc_and_fund_from_owned(
+        &self,
+        new_len: usize,
+        zero_init: bool,
+        payer: &NoStdAccountInfo,
+        rent: &Rent,
+    ) -> Result<(), ProgramError> {
+        if self == payer {
+            return Err(ProgramError::InvalidArgument);
+        }
+
+        let mut lamports = self.try_borrow_mut_lamports()?;
+        let mut payer_lamports = payer.try_borrow_mut_lamports()?;
+
+        let top_up = rent
+            .minimum_balance(new_len)
+            .saturating_sub(*lamports);
+        let new_payer_lamports = payer_lamports
+            .checked_sub(top_up)
+            .ok_or(ProgramError::InsufficientFunds)?;
+
+        // This is the last fallible step
+        self.realloc(new_len, zero_init)?;
+
+        *payer_lamports = new_payer_lamports;
+        *lamports += top_up;
+
+        Ok(())
+    }
+}
+
+#[test]
+fn test_realloc_and_fund() {
+    use crate::{cpi::take_invoke, entrypoint_nostd::TestAccount};
+
+    let rent = Rent::default();
+    assert_eq!(rent.minimum_balance(0), 890_880);
+
+    let (_buffer, account) = TestAccount {
+        key: Pubkey::new_from_array([2; 32]),
+        lamports: rent.minimum_balance(8),
+        data_len: 8,
+        ..Default::default()
+    }
+    .build();
+    let (_payer_buffer, payer) = TestAccount {
+        owner: system::ID,
+        is_signer: true,
+        lamports: 1_000_000,
+        ..Default::default()
+    }
+    .build();
+    assert!(account.is_rent_exempt(&rent).unwrap());
+    assert_eq!(
+        account.realloc_and_fund(8, false, &account.clone(), &rent),
+        Err(ProgramError::InvalidArgument)
+    );
+
+    // The system program moves the lamports
+    account
+        .realloc_and_fund(8 + 100, false, &payer, &rent)
+        .unwrap();
+    assert_eq!(account.data_len(), 108);
+    let invoked = take_invoke().unwrap();
+    assert_eq!(invoked.program_id, system::ID);
+    assert_eq!(
+        invoked.accounts,
+        [(*payer.key(), true, true), (*account.key(), true, false)]
+    );
+    let mut data = [2, 0, 0, 0].to_vec();
+    data.extend((100 * 2 * 3_480_u64).to_le_bytes());
+    assert_eq!(invoked.data, data);
+
+    // Shrinking never moves lamports
+    account
+        .realloc_and_fund(0, false, &payer, &rent)
+        .unwrap();
+    assert_eq!(account.data_len(), 0);
+    assert_eq!(take_invoke(), None);
+}
+
+#[test]
+fn test_realloc_and_fund_from_owned() {
+    let rent = Rent::default();
+    let (_buffer, account) = crate::entrypoint_nostd::test_account(
+        rent.minimum_balance(8),
+        8,
+    );
+    let (_payer_buffer, payer) =
+        crate::entrypoint_nostd::test_account(1_000_000, 0);
+
+    // Not enough to fund the new length, nothing changes
+    assert_eq!(
+        account.realloc_and_fund_from_owned(
+            8 + 1_000,
+            false,
+            &payer,
+            &rent
+        ),
+        Err(ProgramError::InsufficientFunds)
+    );
+    assert_eq!(account.data_len(), 8);
+
+    account
+        .realloc_and_fund_from_owned(8 + 100, false, &payer, &rent)
+        .unwrap();
+    assert_eq!(account.data_len(), 108);
+    assert_eq!(
+        *account.try_borrow_lamports().unwrap(),
+        rent.minimum_balance(108)
+    );
+    assert_eq!(
+        *payer.try_borrow_lamports().unwrap(),
+        1_000_000 - 100 * 2 * 3_480
+    );
+
+    // Shrinking never moves lamports
+    account
+        .realloc_and_fund_from_owned(0, false, &payer, &rent)
+        .unwrap();
+    assert_eq!(
+        *account.try_borrow_lamports().unwrap(),
+        rent.minimum_balance(108)
+    );
+    assert_eq!(crate::cpi::take_invoke(), None);
+}
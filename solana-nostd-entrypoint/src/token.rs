@@ -0,0 +1,756 @@
+//! Cross-program invocations of the SPL Token program, without
+//! depending on `spl-token`. Enabled by the `token` feature.
+//!
+//! The base instructions are shared with Token-2022, so each helper
+//! takes the token program to invoke, e.g. the owner of the mint. See
+//! [`token_2022`] for extension instructions.
+//!
+//! Like the [`system`](crate::system) helpers, each instruction is a
+//! struct whose `invoke` and `invoke_signed` methods encode its data
+//! into a fixed-size stack buffer and go through
+//! [`invoke_signed_checked`]. Multisig authorities are not supported.
+//!
+//! [`TokenAccount`] and [`Mint`] read token program state in place.
+
+use {
+    crate::{
+        cpi::{
+            impl_invoke, invoke_signed_checked, AccountMetaRef,
+            InstructionRef, Signers,
+        },
+        token_2022, DataRef, NoStdAccountInfo,
+    },
+    solana_program_error::{ProgramError, ProgramResult},
+    solana_pubkey::Pubkey,
+};
+
+/// The SPL Token program id.
+pub const ID: Pubkey = solana_pubkey::pubkey!(
+    "TokenkegQfeZyiNwAJbNbGKPFXCWuBvf9Ss623VQ5DA"
+);
+
+/// Fails with [`ProgramError::IncorrectProgramId`] unless `program_id`
+/// is one of the token programs.
+#[inline(always)]
+pub fn check_token_program(
+    program_id: &Pubkey,
+) -> Result<(), ProgramError> {
+    if *program_id != ID && *program_id != token_2022::ID {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+    Ok(())
+}
+
+/// Private: invokes the token program `program_id`.
+#[inline(always)]
+fn invoke_token<const N: usize>(
+    program_id: &Pubkey,
+    accounts: [&NoStdAccountInfo; N],
+    metas: &[AccountMetaRef],
+    data: &[u8],
+    signers: &Signers,
+) -> ProgramResult {
+    check_token_program(program_id)?;
+    let instruction = InstructionRef::new(program_id, metas, data);
+    invoke_signed_checked(&instruction, accounts, signers)
+}
+
+/// Private: a discriminant followed by a u64 amount.
+#[inline(always)]
+fn amount_data(discriminant: u8, amount: u64) -> [u8; 9] {
+    let mut data = [discriminant; 9];
+    data[1..].copy_from_slice(&amount.to_le_bytes());
+    data
+}
+
+/// Transfers tokens between accounts of the same mint.
+pub struct Transfer<'a> {
+    /// The token program, [`ID`] or [`token_2022::ID`].
+    pub token_program: &'a Pubkey,
+    /// Source token account. Writable.
+    pub from: &'a NoStdAccountInfo,
+    /// Destination token account. Writable.
+    pub to: &'a NoStdAccountInfo,
+    /// Owner or delegate of the source account. Signer.
+    pub authority: &'a NoStdAccountInfo,
+    /// Amount of tokens to transfer.
+    pub amount: u64,
+}
+
+impl_invoke!(Transfer);
+
+impl Transfer<'_> {
+    #[inline]
+    pub fn invoke_signed(&self, signers: &Signers) -> ProgramResult {
+        invoke_token(
+            self.token_program,
+            [self.from, self.to, self.authority],
+            &[
+                AccountMetaRef::writable(self.from.key()),
+                AccountMetaRef::writable(self.to.key()),
+                AccountMetaRef::readonly_signer(self.authority.key()),
+            ],
+            &amount_data(3, self.amount),
+            signers,
+        )
+    }
+}
+
+/// Approves a delegate to transfer up to `amount` tokens.
+pub struct Approve<'a> {
+    /// The token program, [`ID`] or [`token_2022::ID`].
+    pub token_program: &'a Pubkey,
+    /// Source token account. Writable.
+    pub source: &'a NoStdAccountInfo,
+    /// Delegate to approve.
+    pub delegate: &'a NoStdAccountInfo,
+    /// Owner of the source account. Signer.
+    pub authority: &'a NoStdAccountInfo,
+    /// Amount of tokens the delegate may transfer.
+    pub amount: u64,
+}
+
+impl_invoke!(Approve);
+
+impl Approve<'_> {
+    #[inline]
+    pub fn invoke_signed(&self, signers: &Signers) -> ProgramResult {
+        invoke_token(
+            self.token_program,
+            [self.source, self.delegate, self.authority],
+            &[
+                AccountMetaRef::writable(self.source.key()),
+                AccountMetaRef::readonly(self.delegate.key()),
+                AccountMetaRef::readonly_signer(self.authority.key()),
+            ],
+            &amount_data(4, self.amount),
+            signers,
+        )
+    }
+}
+
+/// Mints new tokens to an account.
+pub struct MintTo<'a> {
+    /// The token program, [`ID`] or [`token_2022::ID`].
+    pub token_program: &'a Pubkey,
+    /// The mint. Writable.
+    pub mint: &'a NoStdAccountInfo,
+    /// Token account to mint to. Writable.
+    pub account: &'a NoStdAccountInfo,
+    /// Mint authority. Signer.
+    pub authority: &'a NoStdAccountInfo,
+    /// Amount of tokens to mint.
+    pub amount: u64,
+}
+
+impl_invoke!(MintTo);
+
+impl MintTo<'_> {
+    #[inline]
+    pub fn invoke_signed(&self, signers: &Signers) -> ProgramResult {
+        invoke_token(
+            self.token_program,
+            [self.mint, self.account, self.authority],
+            &[
+                AccountMetaRef::writable(self.mint.key()),
+                AccountMetaRef::writable(self.account.key()),
+                AccountMetaRef::readonly_signer(self.authority.key()),
+            ],
+            &amount_data(7, self.amount),
+            signers,
+        )
+    }
+}
+
+/// Burns tokens from an account.
+pub struct Burn<'a> {
+    /// The token program, [`ID`] or [`token_2022::ID`].
+    pub token_program: &'a Pubkey,
+    /// Token account to burn from. Writable.
+    pub account: &'a NoStdAccountInfo,
+    /// The mint. Writable.
+    pub mint: &'a NoStdAccountInfo,
+    /// Owner or delegate of the account. Signer.
+    pub authority: &'a NoStdAccountInfo,
+    /// Amount of tokens to burn.
+    pub amount: u64,
+}
+
+impl_invoke!(Burn);
+
+impl Burn<'_> {
+    #[inline]
+    pub fn invoke_signed(&self, signers: &Signers) -> ProgramResult {
+        invoke_token(
+            self.token_program,
+            [self.account, self.mint, self.authority],
+            &[
+                AccountMetaRef::writable(self.account.key()),
+                AccountMetaRef::writable(self.mint.key()),
+                AccountMetaRef::readonly_signer(self.authority.key()),
+            ],
+            &amount_data(8, self.amount),
+            signers,
+        )
+    }
+}
+
+/// Closes a token account with a zero balance, moving its lamports to
+/// `destination`.
+pub struct CloseAccount<'a> {
+    /// The token program, [`ID`] or [`token_2022::ID`].
+    pub token_program: &'a Pubkey,
+    /// Token account to close. Writable.
+    pub account: &'a NoStdAccountInfo,
+    /// Recipient of the account's lamports. Writable.
+    pub destination: &'a NoStdAccountInfo,
+    /// Owner or close authority of the account. Signer.
+    pub authority: &'a NoStdAccountInfo,
+}
+
+impl_invoke!(CloseAccount);
+
+impl CloseAccount<'_> {
+    #[inline]
+    pub fn invoke_signed(&self, signers: &Signers) -> ProgramResult {
+        invoke_token(
+            self.token_program,
+            [self.account, self.destination, self.authority],
+            &[
+                AccountMetaRef::writable(self.account.key()),
+                AccountMetaRef::writable(self.destination.key()),
+                AccountMetaRef::readonly_signer(self.authority.key()),
+            ],
+            &[9],
+            signers,
+        )
+    }
+}
+
+/// Transfers tokens between accounts of the same mint, checking the
+/// mint and its decimals.
+pub struct TransferChecked<'a> {
+    /// The token program, [`ID`] or [`token_2022::ID`].
+    pub token_program: &'a Pubkey,
+    /// Source token account. Writable.
+    pub from: &'a NoStdAccountInfo,
+    /// The mint.
+    pub mint: &'a NoStdAccountInfo,
+    /// Destination token account. Writable.
+    pub to: &'a NoStdAccountInfo,
+    /// Owner or delegate of the source account. Signer.
+    pub authority: &'a NoStdAccountInfo,
+    /// Amount of tokens to transfer.
+    pub amount: u64,
+    /// Expected decimals of the mint.
+    pub decimals: u8,
+}
+
+impl_invoke!(TransferChecked);
+
+impl TransferChecked<'_> {
+    #[inline(always)]
+    pub(crate) fn data(&self) -> [u8; 10] {
+        let mut data = [0; 10];
+        data[..9].copy_from_slice(&amount_data(12, self.amount));
+        data[9] = self.decimals;
+        data
+    }
+
+    #[inline]
+    pub fn invoke_signed(&self, signers: &Signers) -> ProgramResult {
+        invoke_token(
+            self.token_program,
+            [self.from, self.mint, self.to, self.authority],
+            &[
+                AccountMetaRef::writable(self.from.key()),
+                AccountMetaRef::readonly(self.mint.key()),
+                AccountMetaRef::writable(self.to.key()),
+                AccountMetaRef::readonly_signer(self.authority.key()),
+            ],
+            &self.data(),
+            signers,
+        )
+    }
+}
+
+/// Syncs the token balance of a native (wrapped SOL) account with its
+/// lamports.
+pub struct SyncNative<'a> {
+    /// The token program, [`ID`] or [`token_2022::ID`].
+    pub token_program: &'a Pubkey,
+    /// Native token account. Writable.
+    pub account: &'a NoStdAccountInfo,
+}
+
+impl_invoke!(SyncNative);
+
+impl SyncNative<'_> {
+    #[inline]
+    pub fn invoke_signed(&self, signers: &Signers) -> ProgramResult {
+        invoke_token(
+            self.token_program,
+            [self.account],
+            &[AccountMetaRef::writable(self.account.key())],
+            &[17],
+            signers,
+        )
+    }
+}
+
+/// Initializes a token account for `mint` owned by `owner`, without
+/// requiring the rent sysvar account.
+pub struct InitializeAccount3<'a> {
+    /// The token program, [`ID`] or [`token_2022::ID`].
+    pub token_program: &'a Pubkey,
+    /// Token account to initialize. Writable.
+    pub account: &'a NoStdAccountInfo,
+    /// The mint.
+    pub mint: &'a NoStdAccountInfo,
+    /// Owner of the new token account.
+    pub owner: &'a Pubkey,
+}
+
+impl_invoke!(InitializeAccount3);
+
+impl InitializeAccount3<'_> {
+    #[inline(always)]
+    fn data(&self) -> [u8; 33] {
+        let mut data = [18; 33];
+        data[1..].copy_from_slice(self.owner.as_ref());
+        data
+    }
+
+    #[inline]
+    pub fn invoke_signed(&self, signers: &Signers) -> ProgramResult {
+        invoke_token(
+            self.token_program,
+            [self.account, self.mint],
+            &[
+                AccountMetaRef::writable(self.account.key()),
+                AccountMetaRef::readonly(self.mint.key()),
+            ],
+            &self.data(),
+            signers,
+        )
+    }
+}
+
+/// The length of a token account's base state.
+pub const ACCOUNT_LEN: usize = 165;
+
+/// The length of a mint's base state.
+pub const MINT_LEN: usize = 82;
+
+/// Where Token-2022 stores the account type of accounts and mints with
+/// extensions, which are padded to [`ACCOUNT_LEN`] before it.
+pub const ACCOUNT_TYPE_OFFSET: usize = ACCOUNT_LEN;
+
+/// The state of a token account.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[repr(u8)]
+pub enum AccountState {
+    Uninitialized,
+    Initialized,
+    Frozen,
+}
+
+/// Private: checks the owner, length and account type of token program
+/// state with base length `base_len` and Token-2022 account type
+/// `account_type`.
+#[inline(always)]
+fn check_state(
+    account: &NoStdAccountInfo,
+    base_len: usize,
+    account_type: u8,
+) -> Result<DataRef<'_>, ProgramError> {
+    check_token_program(account.owner())?;
+    let data = account.try_borrow_data()?;
+    let valid = data.len() == base_len
+        || (*account.owner() == token_2022::ID
+            && data.len() > ACCOUNT_TYPE_OFFSET
+            && data[ACCOUNT_TYPE_OFFSET] == account_type);
+    if !valid {
+        return Err(ProgramError::InvalidAccountData);
+    }
+    Ok(data)
+}
+
+/// Private: reads a `Pubkey` at `offset`, which must be in bounds.
+#[inline(always)]
+unsafe fn pubkey_at(data: &[u8], offset: usize) -> &Pubkey {
+    &*(data.as_ptr().add(offset) as *const Pubkey)
+}
+
+/// Private: reads a `u64` at `offset`, which must be in bounds.
+#[inline(always)]
+unsafe fn u64_at(data: &[u8], offset: usize) -> u64 {
+    u64::from_le(core::ptr::read_unaligned(
+        data.as_ptr().add(offset) as *const u64
+    ))
+}
+
+/// Private: reads a `u32` at `offset`, which must be in bounds.
+#[inline(always)]
+unsafe fn u32_at(data: &[u8], offset: usize) -> u32 {
+    u32::from_le(core::ptr::read_unaligned(
+        data.as_ptr().add(offset) as *const u32
+    ))
+}
+
+/// Private: checks that the `COption` tags at `offsets`, which must be
+/// in bounds, are 0 (`None`) or 1 (`Some`), failing with
+/// [`ProgramError::InvalidAccountData`] otherwise.
+#[inline(always)]
+fn check_option_tags(
+    data: &[u8],
+    offsets: &[usize],
+) -> Result<(), ProgramError> {
+    for &offset in offsets {
+        if unsafe { u32_at(data, offset) } > 1 {
+            return Err(ProgramError::InvalidAccountData);
+        }
+    }
+    Ok(())
+}
+
+/// Private: reads a `COption<Pubkey>` at `offset`, which must be in
+/// bounds and have a checked tag.
+#[inline(always)]
+unsafe fn option_pubkey_at(
+    data: &[u8],
+    offset: usize,
+) -> Option<&Pubkey> {
+    match u32_at(data, offset) {
+        0 => None,
+        _ => Some(pubkey_at(data, offset + 4)),
+    }
+}
+
+/// A token account of either token program, read in place.
+///
+/// This holds a shared borrow of the account's data for as long as it
+/// lives.
+pub struct TokenAccount<'a> {
+    data: DataRef<'a>,
+}
+
+impl<'a> TokenAccount<'a> {
+    /// Borrows `account`'s data as an initialized token account.
+    ///
+    /// Fails with [`ProgramError::IncorrectProgramId`] if `account`
+    /// isn't owned by a token program, with
+    /// [`ProgramError::InvalidAccountData`] if it isn't a well-formed
+    /// token account, and with [`ProgramError::UninitializedAccount`]
+    /// if it isn't initialized.
+    pub fn from_account_info(
+        account: &'a NoStdAccountInfo,
+    ) -> Result<TokenAccount<'a>, ProgramError> {
+        let data = check_state(account, ACCOUNT_LEN, 2)?;
+        match data[108] {
+            1 | 2 => {
+                check_option_tags(&data, &[72, 109, 129])?;
+                Ok(TokenAccount { data })
+            }
+            0 => Err(ProgramError::UninitializedAccount),
+            _ => Err(ProgramError::InvalidAccountData),
+        }
+    }
+
+    #[inline(always)]
+    pub fn mint(&self) -> &Pubkey {
+        unsafe { pubkey_at(&self.data, 0) }
+    }
+
+    #[inline(always)]
+    pub fn owner(&self) -> &Pubkey {
+        unsafe { pubkey_at(&self.data, 32) }
+    }
+
+    #[inline(always)]
+    pub fn amount(&self) -> u64 {
+        unsafe { u64_at(&self.data, 64) }
+    }
+
+    #[inline(always)]
+    pub fn delegate(&self) -> Option<&Pubkey> {
+        unsafe { option_pubkey_at(&self.data, 72) }
+    }
+
+    #[inline(always)]
+    pub fn state(&self) -> AccountState {
+        match self.data[108] {
+            1 => AccountState::Initialized,
+            _ => AccountState::Frozen,
+        }
+    }
+
+    #[inline(always)]
+    pub fn is_frozen(&self) -> bool {
+        self.state() == AccountState::Frozen
+    }
+
+    /// The rent exempt reserve of a native (wrapped SOL) account.
+    #[inline(always)]
+    pub fn is_native(&self) -> Option<u64> {
+        match unsafe { u32_at(&self.data, 109) } {
+            0 => None,
+            _ => Some(unsafe { u64_at(&self.data, 113) }),
+        }
+    }
+
+    #[inline(always)]
+    pub fn delegated_amount(&self) -> u64 {
+        unsafe { u64_at(&self.data, 121) }
+    }
+
+    #[inline(always)]
+    pub fn close_authority(&self) -> Option<&Pubkey> {
+        unsafe { option_pubkey_at(&self.data, 129) }
+    }
+
+    /// The whole account data, including any Token-2022 extensions.
+    #[inline(always)]
+    pub fn data(&self) -> &[u8] {
+        &self.data
+    }
+}
+
+/// A mint of either token program, read in place.
+///
+/// This holds a shared borrow of the account's data for as long as it
+/// lives.
+pub struct Mint<'a> {
+    data: DataRef<'a>,
+}
+
+impl<'a> Mint<'a> {
+    /// Borrows `account`'s data as an initialized mint.
+    ///
+    /// Fails with [`ProgramError::IncorrectProgramId`] if `account`
+    /// isn't owned by a token program, with
+    /// [`ProgramError::InvalidAccountData`] if it isn't a well-formed
+    /// mint, and with [`ProgramError::UninitializedAccount`] if it
+    /// isn't initialized.
+    pub fn from_account_info(
+        account: &'a NoStdAccountInfo,
+    ) -> Result<Mint<'a>, ProgramError> {
+        let data = check_state(account, MINT_LEN, 1)?;
+        match data[45] {
+            1 => {
+                check_option_tags(&data, &[0, 46])?;
+                Ok(Mint { data })
+            }
+            0 => Err(ProgramError::UninitializedAccount),
+            _ => Err(ProgramError::InvalidAccountData),
+        }
+    }
+
+    #[inline(always)]
+    pub fn mint_authority(&self) -> Option<&Pubkey> {
+        unsafe { option_pubkey_at(&self.data, 0) }
+    }
+
+    #[inline(always)]
+    pub fn supply(&self) -> u64 {
+        unsafe { u64_at(&self.data, 36) }
+    }
+
+    #[inline(always)]
+    pub fn decimals(&self) -> u8 {
+        self.data[44]
+    }
+
+    #[inline(always)]
+    pub fn freeze_authority(&self) -> Option<&Pubkey> {
+        unsafe { option_pubkey_at(&self.data, 46) }
+    }
+
+    /// The whole account data, including any Token-2022 extensions.
+    #[inline(always)]
+    pub fn data(&self) -> &[u8] {
+        &self.data
+    }
+}
+
+#[test]
+fn test_token_data() {
+    assert_eq!(amount_data(3, 0x0102), [3, 2, 1, 0, 0, 0, 0, 0, 0]);
+
+    let (_buffer, account) =
+        crate::entrypoint_nostd::test_account(5, 0);
+    assert_eq!(check_token_program(&ID), Ok(()));
+    assert_eq!(check_token_program(&token_2022::ID), Ok(()));
+    assert_eq!(
+        check_token_program(account.owner()),
+        Err(ProgramError::IncorrectProgramId)
+    );
+
+    let transfer = TransferChecked {
+        token_program: &ID,
+        from: &account,
+        mint: &account,
+        to: &account,
+        authority: &account,
+        amount: 7,
+        decimals: 6,
+    };
+    assert_eq!(transfer.data(), [12, 7, 0, 0, 0, 0, 0, 0, 0, 6]);
+
+    let (_buffers, [from, mint, to, authority]) =
+        crate::entrypoint_nostd::test_accounts();
+    let transfer = TransferChecked {
+        token_program: &token_2022::ID,
+        from: &from,
+        mint: &mint,
+        to: &to,
+        authority: &authority,
+        ..transfer
+    };
+    transfer.invoke().unwrap();
+    let invoked = crate::cpi::take_invoke().unwrap();
+    assert_eq!(invoked.program_id, token_2022::ID);
+    assert_eq!(
+        invoked.accounts,
+        [
+            (*from.key(), true, false),
+            (*mint.key(), false, false),
+            (*to.key(), true, false),
+            (*authority.key(), false, true),
+        ]
+    );
+    assert_eq!(invoked.data, transfer.data());
+
+    let owner = Pubkey::new_from_array([4; 32]);
+    let initialize = InitializeAccount3 {
+        token_program: &ID,
+        account: &account,
+        mint: &account,
+        owner: &owner,
+    };
+    let data = initialize.data();
+    assert_eq!(data[0], 18);
+    assert_eq!(data[1..], [4; 32]);
+}
+
+#[test]
+fn test_token_views() {
+    use crate::entrypoint_nostd::TestAccount;
+
+    // Owned by neither token program
+    let (_buffer, account) =
+        crate::entrypoint_nostd::test_account(5, ACCOUNT_LEN);
+    assert_eq!(
+        TokenAccount::from_account_info(&account).err(),
+        Some(ProgramError::IncorrectProgramId)
+    );
+
+    let (_buffer, account) = TestAccount {
+        owner: ID,
+        lamports: 5,
+        data_len: ACCOUNT_LEN,
+        ..Default::default()
+    }
+    .build();
+    let (_mint_buffer, mint) = TestAccount {
+        owner: token_2022::ID,
+        lamports: 5,
+        data_len: MINT_LEN,
+        ..Default::default()
+    }
+    .build();
+    assert_eq!(
+        TokenAccount::from_account_info(&account).err(),
+        Some(ProgramError::UninitializedAccount)
+    );
+
+    // A token account isn't a mint, nor the other way around
+    assert_eq!(
+        Mint::from_account_info(&account).err(),
+        Some(ProgramError::InvalidAccountData)
+    );
+    assert_eq!(
+        TokenAccount::from_account_info(&mint).err(),
+        Some(ProgramError::InvalidAccountData)
+    );
+
+    {
+        let mut data = account.try_borrow_mut_data().unwrap();
+        data[..32].copy_from_slice(&[1; 32]);
+        data[32..64].copy_from_slice(&[2; 32]);
+        data[64..72].copy_from_slice(&1_000_u64.to_le_bytes());
+        data[72] = 1;
+        data[76..108].copy_from_slice(&[3; 32]);
+        data[108] = 2;
+        data[121..129].copy_from_slice(&10_u64.to_le_bytes());
+    }
+    let token_account =
+        TokenAccount::from_account_info(&account).unwrap();
+    assert_eq!(token_account.mint().as_ref(), &[1; 32]);
+    assert_eq!(token_account.owner().as_ref(), &[2; 32]);
+    assert_eq!(token_account.amount(), 1_000);
+    assert_eq!(
+        token_account
+            .delegate()
+            .unwrap()
+            .as_ref(),
+        &[3; 32]
+    );
+    assert!(token_account.is_frozen());
+    assert_eq!(token_account.is_native(), None);
+    assert_eq!(token_account.delegated_amount(), 10);
+    assert_eq!(token_account.close_authority(), None);
+    assert!(account.try_borrow_mut_data().is_err());
+    drop(token_account);
+
+    // `COption` tags are a whole u32, not just their first byte
+    account.try_borrow_mut_data().unwrap()[72..76]
+        .copy_from_slice(&[0, 1, 0, 0]);
+    assert_eq!(
+        TokenAccount::from_account_info(&account).err(),
+        Some(ProgramError::InvalidAccountData)
+    );
+    account.try_borrow_mut_data().unwrap()[72..76]
+        .copy_from_slice(&[1, 0, 0, 0]);
+
+    {
+        let mut data = mint.try_borrow_mut_data().unwrap();
+        data[36..44].copy_from_slice(&7_u64.to_le_bytes());
+        data[44] = 6;
+        data[45] = 1;
+        data[46] = 1;
+        data[50..82].copy_from_slice(&[4; 32]);
+        data[0..4].copy_from_slice(&[2, 0, 0, 0]);
+    }
+    assert_eq!(
+        Mint::from_account_info(&mint).err(),
+        Some(ProgramError::InvalidAccountData)
+    );
+    mint.try_borrow_mut_data().unwrap()[0] = 0;
+    let view = Mint::from_account_info(&mint).unwrap();
+    assert_eq!(view.mint_authority(), None);
+    assert_eq!(view.supply(), 7);
+    assert_eq!(view.decimals(), 6);
+    assert_eq!(
+        view.freeze_authority()
+            .unwrap()
+            .as_ref(),
+        &[4; 32]
+    );
+    drop(view);
+
+    // Token-2022 mints with extensions carry their account type
+    mint.realloc(ACCOUNT_TYPE_OFFSET + 1, true)
+        .unwrap();
+    assert_eq!(
+        Mint::from_account_info(&mint).err(),
+        Some(ProgramError::InvalidAccountData)
+    );
+    mint.try_borrow_mut_data().unwrap()[ACCOUNT_TYPE_OFFSET] = 1;
+    assert_eq!(
+        Mint::from_account_info(&mint)
+            .unwrap()
+            .decimals(),
+        6
+    );
+}
@@ -0,0 +1,788 @@
+//! Cross-program invocations of the Token-2022 program. Enabled by the
+//! `token` feature.
+//!
+//! Token-2022 shares SPL Token's base instructions, which are
+//! re-exported here and invoke whichever program their
+//! `token_program` names. On top of those, this module adds extension
+//! instructions, and transfers that pass trailing accounts along for
+//! the mint's transfer hook.
+//!
+//! [`Extensions`] walks the extensions of a [`Mint`] or
+//! [`TokenAccount`] in place, and [`Extension`]s view their values.
+
+pub use crate::token::{
+    Approve, Burn, CloseAccount, InitializeAccount3, MintTo,
+    SyncNative, Transfer, TransferChecked,
+};
+use {
+    crate::{
+        cpi::{
+            impl_invoke, invoke_signed_checked,
+            invoke_signed_checked_slice, AccountMetaRef,
+            InstructionRef, Signers,
+        },
+        token::{
+            check_token_program, Mint, TokenAccount,
+            ACCOUNT_TYPE_OFFSET,
+        },
+        NoStdAccountInfo,
+    },
+    solana_program_error::{ProgramError, ProgramResult},
+    solana_pubkey::Pubkey,
+};
+
+/// The Token-2022 program id.
+pub const ID: Pubkey = solana_pubkey::pubkey!(
+    "TokenzQdBNbLqP5VEhdkAS6EPFLC1PHnBqCXEpPxuEb"
+);
+
+/// The most trailing accounts a transfer can pass along, e.g. the
+/// transfer hook program, its extra account metas account and the
+/// extra accounts themselves.
+pub const MAX_REMAINING_ACCOUNTS: usize = 16;
+
+/// The most extension types a [`Reallocate`] can make room for.
+pub const MAX_REALLOCATE_EXTENSIONS: usize = 16;
+
+/// Private: the most accounts any instruction here takes before its
+/// trailing accounts.
+const MAX_BASE_ACCOUNTS: usize = 4;
+
+/// Private: invokes the token program `program_id` with `accounts`
+/// followed by `remaining`, which keep the privileges they were passed
+/// to this program with.
+#[inline(always)]
+fn invoke_with_remaining<const N: usize>(
+    program_id: &Pubkey,
+    accounts: [&NoStdAccountInfo; N],
+    metas: [AccountMetaRef; N],
+    data: &[u8],
+    remaining: &[NoStdAccountInfo],
+    signers: &Signers,
+) -> ProgramResult {
+    const MAX: usize = MAX_BASE_ACCOUNTS + MAX_REMAINING_ACCOUNTS;
+    debug_assert!(N <= MAX_BASE_ACCOUNTS);
+
+    check_token_program(program_id)?;
+    if remaining.len() > MAX_REMAINING_ACCOUNTS {
+        return Err(ProgramError::InvalidArgument);
+    }
+    let len = N + remaining.len();
+
+    // Entries past `len` are never read
+    let all_accounts: [&NoStdAccountInfo; MAX] =
+        core::array::from_fn(|i| match i.checked_sub(N) {
+            None => accounts[i],
+            Some(j) => remaining.get(j).unwrap_or(accounts[0]),
+        });
+    let all_metas: [AccountMetaRef; MAX] =
+        core::array::from_fn(|i| match i.checked_sub(N) {
+            None => metas[i].clone(),
+            Some(j) => remaining.get(j).map_or_else(
+                || metas[0].clone(),
+                |account| account.to_meta_ref(),
+            ),
+        });
+
+    let instruction =
+        InstructionRef::new(program_id, &all_metas[..len], data);
+    invoke_signed_checked_slice::<MAX>(
+        &instruction,
+        &all_accounts[..len],
+        signers,
+    )
+}
+
+impl TransferChecked<'_> {
+    /// Like [`TransferChecked::invoke`], passing `remaining` along for
+    /// the mint's transfer hook.
+    #[inline]
+    pub fn invoke_with_remaining(
+        &self,
+        remaining: &[NoStdAccountInfo],
+    ) -> ProgramResult {
+        self.invoke_signed_with_remaining(remaining, &[])
+    }
+
+    /// Like [`TransferChecked::invoke_signed`], passing `remaining`
+    /// along for the mint's transfer hook.
+    ///
+    /// The trailing accounts keep the privileges they were passed to
+    /// this program with, and there may be at most
+    /// [`MAX_REMAINING_ACCOUNTS`] of them.
+    pub fn invoke_signed_with_remaining(
+        &self,
+        remaining: &[NoStdAccountInfo],
+        signers: &Signers,
+    ) -> ProgramResult {
+        invoke_with_remaining(
+            self.token_program,
+            [self.from, self.mint, self.to, self.authority],
+            [
+                AccountMetaRef::writable(self.from.key()),
+                AccountMetaRef::readonly(self.mint.key()),
+                AccountMetaRef::writable(self.to.key()),
+                AccountMetaRef::readonly_signer(self.authority.key()),
+            ],
+            &self.data(),
+            remaining,
+            signers,
+        )
+    }
+}
+
+/// Transfers tokens from a mint with the transfer fee extension,
+/// checking the expected fee.
+pub struct TransferCheckedWithFee<'a> {
+    /// Source token account. Writable.
+    pub from: &'a NoStdAccountInfo,
+    /// The mint.
+    pub mint: &'a NoStdAccountInfo,
+    /// Destination token account. Writable.
+    pub to: &'a NoStdAccountInfo,
+    /// Owner or delegate of the source account. Signer.
+    pub authority: &'a NoStdAccountInfo,
+    /// Amount of tokens to transfer, including the fee.
+    pub amount: u64,
+    /// Expected decimals of the mint.
+    pub decimals: u8,
+    /// Expected fee withheld from the transfer.
+    pub fee: u64,
+}
+
+impl_invoke!(TransferCheckedWithFee);
+
+impl TransferCheckedWithFee<'_> {
+    #[inline(always)]
+    fn data(&self) -> [u8; 19] {
+        let mut data = [0; 19];
+        data[..2].copy_from_slice(&[26, 1]);
+        data[2..10].copy_from_slice(&self.amount.to_le_bytes());
+        data[10] = self.decimals;
+        data[11..].copy_from_slice(&self.fee.to_le_bytes());
+        data
+    }
+
+    #[inline]
+    pub fn invoke_signed(&self, signers: &Signers) -> ProgramResult {
+        self.invoke_signed_with_remaining(&[], signers)
+    }
+
+    /// Like [`TransferCheckedWithFee::invoke`], passing `remaining`
+    /// along for the mint's transfer hook.
+    #[inline]
+    pub fn invoke_with_remaining(
+        &self,
+        remaining: &[NoStdAccountInfo],
+    ) -> ProgramResult {
+        self.invoke_signed_with_remaining(remaining, &[])
+    }
+
+    /// Like [`TransferCheckedWithFee::invoke_signed`], passing
+    /// `remaining` along for the mint's transfer hook.
+    ///
+    /// See [`TransferChecked::invoke_signed_with_remaining`].
+    pub fn invoke_signed_with_remaining(
+        &self,
+        remaining: &[NoStdAccountInfo],
+        signers: &Signers,
+    ) -> ProgramResult {
+        invoke_with_remaining(
+            &ID,
+            [self.from, self.mint, self.to, self.authority],
+            [
+                AccountMetaRef::writable(self.from.key()),
+                AccountMetaRef::readonly(self.mint.key()),
+                AccountMetaRef::writable(self.to.key()),
+                AccountMetaRef::readonly_signer(self.authority.key()),
+            ],
+            &self.data(),
+            remaining,
+            signers,
+        )
+    }
+}
+
+/// Grows a token account to make room for the given extension types.
+pub struct Reallocate<'a> {
+    /// Token account to grow. Writable.
+    pub account: &'a NoStdAccountInfo,
+    /// Funds the additional rent. Writable signer.
+    pub payer: &'a NoStdAccountInfo,
+    /// The system program.
+    pub system_program: &'a NoStdAccountInfo,
+    /// Owner of the token account. Signer.
+    pub owner: &'a NoStdAccountInfo,
+    /// Extension types to make room for, at most
+    /// [`MAX_REALLOCATE_EXTENSIONS`].
+    pub extension_types: &'a [u16],
+}
+
+impl_invoke!(Reallocate);
+
+impl Reallocate<'_> {
+    #[inline(always)]
+    fn data(
+        &self,
+    ) -> Result<
+        ([u8; 1 + 2 * MAX_REALLOCATE_EXTENSIONS], usize),
+        ProgramError,
+    > {
+        if self.extension_types.len() > MAX_REALLOCATE_EXTENSIONS {
+            return Err(ProgramError::InvalidArgument);
+        }
+        let mut data = [29; 1 + 2 * MAX_REALLOCATE_EXTENSIONS];
+        for (bytes, extension_type) in data[1..]
+            .chunks_exact_mut(2)
+            .zip(self.extension_types)
+        {
+            bytes.copy_from_slice(&extension_type.to_le_bytes());
+        }
+        Ok((data, 1 + 2 * self.extension_types.len()))
+    }
+
+    #[inline]
+    pub fn invoke_signed(&self, signers: &Signers) -> ProgramResult {
+        let (data, len) = self.data()?;
+        let metas = [
+            AccountMetaRef::writable(self.account.key()),
+            AccountMetaRef::writable_signer(self.payer.key()),
+            AccountMetaRef::readonly(self.system_program.key()),
+            AccountMetaRef::readonly_signer(self.owner.key()),
+        ];
+        let instruction =
+            InstructionRef::new(&ID, &metas, &data[..len]);
+        invoke_signed_checked(
+            &instruction,
+            [self.account, self.payer, self.system_program, self.owner],
+            signers,
+        )
+    }
+}
+
+/// Initializes the close authority extension of a mint. Must precede
+/// the mint's initialization.
+pub struct InitializeMintCloseAuthority<'a> {
+    /// The mint. Writable.
+    pub mint: &'a NoStdAccountInfo,
+    /// Authority allowed to close the mint, if any.
+    pub close_authority: Option<&'a Pubkey>,
+}
+
+impl_invoke!(InitializeMintCloseAuthority);
+
+impl InitializeMintCloseAuthority<'_> {
+    #[inline(always)]
+    fn data(&self) -> ([u8; 34], usize) {
+        let mut data = [0; 34];
+        data[0] = 25;
+        match self.close_authority {
+            Some(close_authority) => {
+                data[1] = 1;
+                data[2..].copy_from_slice(close_authority.as_ref());
+                (data, 34)
+            }
+            None => (data, 2),
+        }
+    }
+
+    #[inline]
+    pub fn invoke_signed(&self, signers: &Signers) -> ProgramResult {
+        let (data, len) = self.data();
+        let metas = [AccountMetaRef::writable(self.mint.key())];
+        let instruction =
+            InstructionRef::new(&ID, &metas, &data[..len]);
+        invoke_signed_checked(&instruction, [self.mint], signers)
+    }
+}
+
+/// The extension types Token-2022 knows of at the time of writing.
+/// Mints and accounts may carry others, which [`Extensions`] skips.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[repr(u16)]
+pub enum ExtensionType {
+    Uninitialized,
+    TransferFeeConfig,
+    TransferFeeAmount,
+    MintCloseAuthority,
+    ConfidentialTransferMint,
+    ConfidentialTransferAccount,
+    DefaultAccountState,
+    ImmutableOwner,
+    MemoTransfer,
+    NonTransferable,
+    InterestBearingConfig,
+    CpiGuard,
+    PermanentDelegate,
+    NonTransferableAccount,
+    TransferHook,
+    TransferHookAccount,
+    ConfidentialTransferFeeConfig,
+    ConfidentialTransferFeeAmount,
+    MetadataPointer,
+    TokenMetadata,
+    GroupPointer,
+    TokenGroup,
+    GroupMemberPointer,
+    TokenGroupMember,
+}
+
+/// The extensions of a mint or token account, as `(type, value)`
+/// pairs in the order they're stored.
+///
+/// Iteration ends at the end of the data or at zeroed padding, and
+/// yields [`ProgramError::InvalidAccountData`] (once) if an extension's
+/// length runs past the end of the data.
+#[derive(Clone)]
+pub struct Extensions<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> Extensions<'a> {
+    /// The extensions in the data of a mint or token account, which
+    /// must already be validated as such. Data without extensions has
+    /// none.
+    #[inline(always)]
+    pub fn new(data: &'a [u8]) -> Extensions<'a> {
+        Extensions {
+            data: data
+                .get(ACCOUNT_TYPE_OFFSET + 1..)
+                .unwrap_or(&[]),
+        }
+    }
+
+    /// The value of extension `T`, if present.
+    ///
+    /// Fails with [`ProgramError::InvalidAccountData`] if its length
+    /// isn't that of `T`.
+    pub fn get<T: Extension>(
+        self,
+    ) -> Result<Option<&'a T>, ProgramError> {
+        for extension in self {
+            let (extension_type, value) = extension?;
+            if extension_type == T::TYPE as u16 {
+                if value.len() != size_of::<T>() {
+                    return Err(ProgramError::InvalidAccountData);
+                }
+                // SAFETY: `T` is a plain byte layout with alignment 1
+                return Ok(Some(unsafe {
+                    &*(value.as_ptr() as *const T)
+                }));
+            }
+        }
+        Ok(None)
+    }
+
+    /// Whether an extension of type `extension_type` is present.
+    pub fn contains(
+        self,
+        extension_type: ExtensionType,
+    ) -> Result<bool, ProgramError> {
+        for extension in self {
+            if extension?.0 == extension_type as u16 {
+                return Ok(true);
+            }
+        }
+        Ok(false)
+    }
+}
+
+impl<'a> Iterator for Extensions<'a> {
+    type Item = Result<(u16, &'a [u8]), ProgramError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let header = self.data.get(..4)?;
+        let extension_type = u16::from_le_bytes([header[0], header[1]]);
+        if extension_type == ExtensionType::Uninitialized as u16 {
+            return None;
+        }
+        let len = u16::from_le_bytes([header[2], header[3]]) as usize;
+
+        let Some(value) = self.data.get(4..4 + len) else {
+            self.data = &[];
+            return Some(Err(ProgramError::InvalidAccountData));
+        };
+        self.data = &self.data[4 + len..];
+        Some(Ok((extension_type, value)))
+    }
+}
+
+impl Mint<'_> {
+    #[inline(always)]
+    pub fn extensions(&self) -> Extensions<'_> {
+        Extensions::new(self.data())
+    }
+}
+
+impl TokenAccount<'_> {
+    #[inline(always)]
+    pub fn extensions(&self) -> Extensions<'_> {
+        Extensions::new(self.data())
+    }
+}
+
+/// The value of an extension, viewed in place.
+///
+/// # Safety
+/// Implementors must consist of bytes only (alignment 1, no padding,
+/// any bit pattern valid), laid out exactly as Token-2022 stores the
+/// extension.
+pub unsafe trait Extension {
+    const TYPE: ExtensionType;
+}
+
+/// Private: Token-2022's `OptionalNonZeroPubkey`, where zero means
+/// none.
+#[inline(always)]
+fn optional(key: &Pubkey) -> Option<&Pubkey> {
+    (*key != Pubkey::default()).then_some(key)
+}
+
+/// A transfer fee schedule, in effect from `epoch`.
+#[derive(Debug)]
+#[repr(C)]
+pub struct TransferFee {
+    epoch: [u8; 8],
+    maximum_fee: [u8; 8],
+    transfer_fee_basis_points: [u8; 2],
+}
+
+impl TransferFee {
+    #[inline(always)]
+    pub fn epoch(&self) -> u64 {
+        u64::from_le_bytes(self.epoch)
+    }
+
+    #[inline(always)]
+    pub fn maximum_fee(&self) -> u64 {
+        u64::from_le_bytes(self.maximum_fee)
+    }
+
+    #[inline(always)]
+    pub fn transfer_fee_basis_points(&self) -> u16 {
+        u16::from_le_bytes(self.transfer_fee_basis_points)
+    }
+
+    /// The fee withheld from transferring `amount`, rounded up and
+    /// capped at the maximum fee, as Token-2022 computes it.
+    pub fn fee(&self, amount: u64) -> u64 {
+        let basis_points = self.transfer_fee_basis_points() as u128;
+        let fee = (amount as u128 * basis_points).div_ceil(10_000);
+        fee.min(self.maximum_fee() as u128) as u64
+    }
+}
+
+/// A mint's transfer fee configuration.
+#[derive(Debug)]
+#[repr(C)]
+pub struct TransferFeeConfig {
+    transfer_fee_config_authority: Pubkey,
+    withdraw_withheld_authority: Pubkey,
+    withheld_amount: [u8; 8],
+    older_transfer_fee: TransferFee,
+    newer_transfer_fee: TransferFee,
+}
+
+unsafe impl Extension for TransferFeeConfig {
+    const TYPE: ExtensionType = ExtensionType::TransferFeeConfig;
+}
+
+impl TransferFeeConfig {
+    #[inline(always)]
+    pub fn transfer_fee_config_authority(&self) -> Option<&Pubkey> {
+        optional(&self.transfer_fee_config_authority)
+    }
+
+    #[inline(always)]
+    pub fn withdraw_withheld_authority(&self) -> Option<&Pubkey> {
+        optional(&self.withdraw_withheld_authority)
+    }
+
+    #[inline(always)]
+    pub fn withheld_amount(&self) -> u64 {
+        u64::from_le_bytes(self.withheld_amount)
+    }
+
+    #[inline(always)]
+    pub fn older_transfer_fee(&self) -> &TransferFee {
+        &self.older_transfer_fee
+    }
+
+    #[inline(always)]
+    pub fn newer_transfer_fee(&self) -> &TransferFee {
+        &self.newer_transfer_fee
+    }
+
+    /// The fee schedule in effect at `epoch`.
+    #[inline(always)]
+    pub fn transfer_fee(&self, epoch: u64) -> &TransferFee {
+        if epoch >= self.newer_transfer_fee.epoch() {
+            &self.newer_transfer_fee
+        } else {
+            &self.older_transfer_fee
+        }
+    }
+}
+
+/// A mint's transfer hook program.
+#[derive(Debug)]
+#[repr(C)]
+pub struct TransferHook {
+    authority: Pubkey,
+    program_id: Pubkey,
+}
+
+unsafe impl Extension for TransferHook {
+    const TYPE: ExtensionType = ExtensionType::TransferHook;
+}
+
+impl TransferHook {
+    #[inline(always)]
+    pub fn authority(&self) -> Option<&Pubkey> {
+        optional(&self.authority)
+    }
+
+    #[inline(always)]
+    pub fn program_id(&self) -> Option<&Pubkey> {
+        optional(&self.program_id)
+    }
+}
+
+/// Marks a mint's tokens as non-transferable. Carries no data.
+#[derive(Debug)]
+#[repr(C)]
+pub struct NonTransferable;
+
+unsafe impl Extension for NonTransferable {
+    const TYPE: ExtensionType = ExtensionType::NonTransferable;
+}
+
+/// A mint's permanent delegate, which may transfer or burn from any of
+/// its token accounts.
+#[derive(Debug)]
+#[repr(C)]
+pub struct PermanentDelegate {
+    delegate: Pubkey,
+}
+
+unsafe impl Extension for PermanentDelegate {
+    const TYPE: ExtensionType = ExtensionType::PermanentDelegate;
+}
+
+impl PermanentDelegate {
+    #[inline(always)]
+    pub fn delegate(&self) -> Option<&Pubkey> {
+        optional(&self.delegate)
+    }
+}
+
+/// Where a mint's metadata lives.
+#[derive(Debug)]
+#[repr(C)]
+pub struct MetadataPointer {
+    authority: Pubkey,
+    metadata_address: Pubkey,
+}
+
+unsafe impl Extension for MetadataPointer {
+    const TYPE: ExtensionType = ExtensionType::MetadataPointer;
+}
+
+impl MetadataPointer {
+    #[inline(always)]
+    pub fn authority(&self) -> Option<&Pubkey> {
+        optional(&self.authority)
+    }
+
+    #[inline(always)]
+    pub fn metadata_address(&self) -> Option<&Pubkey> {
+        optional(&self.metadata_address)
+    }
+}
+
+#[test]
+fn test_token_2022_data() {
+    let (_buffer, account) =
+        crate::entrypoint_nostd::test_account(5, 0);
+
+    let transfer = TransferCheckedWithFee {
+        from: &account,
+        mint: &account,
+        to: &account,
+        authority: &account,
+        amount: 1,
+        decimals: 6,
+        fee: 2,
+    };
+    assert_eq!(
+        transfer.data(),
+        [26, 1, 1, 0, 0, 0, 0, 0, 0, 0, 6, 2, 0, 0, 0, 0, 0, 0, 0]
+    );
+
+    let mut reallocate = Reallocate {
+        account: &account,
+        payer: &account,
+        system_program: &account,
+        owner: &account,
+        extension_types: &[1, 0x0203],
+    };
+    let (data, len) = reallocate.data().unwrap();
+    assert_eq!(data[..len], [29, 1, 0, 3, 2]);
+    reallocate.extension_types = &[0; MAX_REALLOCATE_EXTENSIONS + 1];
+    assert_eq!(reallocate.invoke(), Err(ProgramError::InvalidArgument));
+
+    let close_authority = Pubkey::new_from_array([5; 32]);
+    let mut initialize = InitializeMintCloseAuthority {
+        mint: &account,
+        close_authority: Some(&close_authority),
+    };
+    let (data, len) = initialize.data();
+    assert_eq!(data[..2], [25, 1]);
+    assert_eq!(data[2..len], [5; 32]);
+    initialize.close_authority = None;
+    let (data, len) = initialize.data();
+    assert_eq!(data[..len], [25, 0]);
+}
+
+#[test]
+fn test_transfer_with_remaining() {
+    use crate::{cpi::take_invoke, entrypoint_nostd::TestAccount};
+
+    let (_buffer, account) =
+        crate::entrypoint_nostd::test_account(5, 0);
+    let transfer = TransferChecked {
+        token_program: &ID,
+        from: &account,
+        mint: &account,
+        to: &account,
+        authority: &account,
+        amount: 1,
+        decimals: 6,
+    };
+
+    let remaining = [account.clone(), account.clone()];
+    assert_eq!(transfer.invoke_with_remaining(&remaining), Ok(()));
+    assert_eq!(take_invoke().unwrap().accounts.len(), 6);
+
+    // Trailing accounts keep their privileges
+    let (_buffer, hook) = TestAccount {
+        key: Pubkey::new_from_array([8; 32]),
+        is_signer: true,
+        is_writable: false,
+        ..Default::default()
+    }
+    .build();
+    transfer
+        .invoke_with_remaining(core::slice::from_ref(&hook))
+        .unwrap();
+    let invoked = take_invoke().unwrap();
+    assert_eq!(invoked.program_id, ID);
+    assert_eq!(invoked.accounts[4], (*hook.key(), false, true));
+    assert_eq!(invoked.infos[4], *hook.key());
+    assert_eq!(invoked.data, transfer.data());
+
+    let too_many: [NoStdAccountInfo; MAX_REMAINING_ACCOUNTS + 1] =
+        core::array::from_fn(|_| account.clone());
+    assert_eq!(
+        transfer.invoke_with_remaining(&too_many),
+        Err(ProgramError::InvalidArgument)
+    );
+
+    // Only the token programs are invoked
+    let transfer = TransferChecked {
+        token_program: account.owner(),
+        ..transfer
+    };
+    assert_eq!(
+        transfer.invoke_with_remaining(&[]),
+        Err(ProgramError::IncorrectProgramId)
+    );
+}
+
+#[test]
+fn test_extensions() {
+    assert_eq!(size_of::<TransferFeeConfig>(), 108);
+    assert_eq!(size_of::<TransferHook>(), 64);
+    assert_eq!(size_of::<PermanentDelegate>(), 32);
+    assert_eq!(size_of::<MetadataPointer>(), 64);
+
+    // Base mint and account type, then the extensions
+    let mut data = [0; 512];
+    data[ACCOUNT_TYPE_OFFSET] = 1;
+    let mut len = ACCOUNT_TYPE_OFFSET + 1;
+    let mut push = |extension_type: u16, value: &[u8]| {
+        data[len..len + 2]
+            .copy_from_slice(&extension_type.to_le_bytes());
+        data[len + 2..len + 4]
+            .copy_from_slice(&(value.len() as u16).to_le_bytes());
+        data[len + 4..len + 4 + value.len()].copy_from_slice(value);
+        len += 4 + value.len();
+    };
+    push(ExtensionType::NonTransferable as u16, &[]);
+    push(999, &[7; 10]);
+    let mut hook = [0; 64];
+    hook[32..].copy_from_slice(&[8; 32]);
+    push(ExtensionType::TransferHook as u16, &hook);
+    let mut fee_config = [0; 108];
+    fee_config[72..80].copy_from_slice(&0_u64.to_le_bytes());
+    fee_config[80..88].copy_from_slice(&5_u64.to_le_bytes());
+    fee_config[88..90].copy_from_slice(&100_u16.to_le_bytes());
+    fee_config[90..98].copy_from_slice(&10_u64.to_le_bytes());
+    fee_config[98..106].copy_from_slice(&1_000_u64.to_le_bytes());
+    fee_config[106..108].copy_from_slice(&50_u16.to_le_bytes());
+    push(ExtensionType::TransferFeeConfig as u16, &fee_config);
+
+    // Unknown extensions are skipped, and the zeroed tail ends it
+    let extensions = Extensions::new(&data);
+    assert_eq!(extensions.clone().count(), 4);
+    assert!(extensions
+        .clone()
+        .contains(ExtensionType::NonTransferable)
+        .unwrap());
+    assert!(extensions
+        .clone()
+        .get::<NonTransferable>()
+        .unwrap()
+        .is_some());
+    assert!(extensions
+        .clone()
+        .get::<PermanentDelegate>()
+        .unwrap()
+        .is_none());
+
+    let hook = extensions
+        .clone()
+        .get::<TransferHook>()
+        .unwrap()
+        .unwrap();
+    assert_eq!(hook.authority(), None);
+    assert_eq!(hook.program_id().unwrap().as_ref(), &[8; 32]);
+
+    let fee_config = extensions
+        .clone()
+        .get::<TransferFeeConfig>()
+        .unwrap()
+        .unwrap();
+    assert_eq!(fee_config.transfer_fee(9).fee(1_000), 5);
+    assert_eq!(fee_config.transfer_fee(9).fee(1), 1);
+    assert_eq!(fee_config.transfer_fee(10).fee(1_000), 5);
+    assert_eq!(
+        fee_config
+            .transfer_fee(10)
+            .fee(1_000_000),
+        1_000
+    );
+
+    // Lengths running past the end are an error, not a panic
+    let truncated = &data[..len - 1];
+    assert_eq!(
+        Extensions::new(truncated)
+            .get::<TransferFeeConfig>()
+            .err(),
+        Some(ProgramError::InvalidAccountData)
+    );
+    assert_eq!(
+        Extensions::new(&data[..ACCOUNT_TYPE_OFFSET]).count(),
+        0
+    );
+}
@@ -0,0 +1,571 @@
+//! Helpers for implementing the SPL transfer hook interface. Enabled
+//! by the `token` feature.
+//!
+//! A hook program receives [`EXECUTE_DISCRIMINATOR`]-tagged
+//! instructions from Token-2022 with the source, mint, destination and
+//! authority accounts, then the mint's [`ExtraAccountMetaList`]
+//! account, then the extra accounts that list describes. A typical
+//! handler checks the extra accounts against the list before doing
+//! anything else:
+//!
+//! ```ignore
+//! let amount = unpack_execute(data)?;
+//! let list = ExtraAccountMetaList::from_account_info(
+//!     &accounts[EXTRA_ACCOUNT_METAS_INDEX],
+//!     mint.key(),
+//!     program_id,
+//! )?;
+//! list.check_accounts(data, accounts, program_id)?;
+//! ```
+
+use {
+    crate::{
+        pda::{find_program_address, try_find_program_address},
+        DataRef, NoStdAccountInfo,
+    },
+    solana_program_error::{ProgramError, ProgramResult},
+    solana_pubkey::{Pubkey, MAX_SEEDS, MAX_SEED_LEN},
+};
+
+/// The discriminator of the `Execute` instruction, and of its entry in
+/// the extra account metas account.
+pub const EXECUTE_DISCRIMINATOR: [u8; 8] =
+    [105, 37, 101, 197, 75, 251, 102, 26];
+
+/// The seed, along with the mint, of the extra account metas account.
+pub const EXTRA_ACCOUNT_METAS_SEED: &[u8] = b"extra-account-metas";
+
+/// The position of the extra account metas account among the `Execute`
+/// instruction's accounts. The extra accounts follow it.
+pub const EXTRA_ACCOUNT_METAS_INDEX: usize = 4;
+
+/// Reads the amount from `Execute` instruction data.
+///
+/// Fails with [`ProgramError::InvalidInstructionData`] if `data` isn't
+/// an `Execute` instruction.
+#[inline]
+pub fn unpack_execute(data: &[u8]) -> Result<u64, ProgramError> {
+    match data {
+        [discriminator @ .., a, b, c, d, e, f, g, h]
+            if *discriminator == EXECUTE_DISCRIMINATOR =>
+        {
+            Ok(u64::from_le_bytes([*a, *b, *c, *d, *e, *f, *g, *h]))
+        }
+        _ => Err(ProgramError::InvalidInstructionData),
+    }
+}
+
+/// Derives the address of `mint`'s extra account metas account for the
+/// hook program `program_id`, along with its bump seed.
+#[inline]
+pub fn extra_account_metas_address(
+    mint: &Pubkey,
+    program_id: &Pubkey,
+) -> (Pubkey, u8) {
+    find_program_address(
+        &[EXTRA_ACCOUNT_METAS_SEED, mint.as_ref()],
+        program_id,
+    )
+}
+
+/// An extra account, as stored in the extra account metas account.
+#[derive(Debug)]
+#[repr(C)]
+pub struct ExtraAccountMeta {
+    discriminator: u8,
+    address_config: [u8; 32],
+    is_signer: u8,
+    is_writable: u8,
+}
+
+impl ExtraAccountMeta {
+    /// How to find the account's address: 0 for a fixed address, 1 for
+    /// a pda of the hook program, 2 for an address read from
+    /// instruction or account data, and `128 + i` for a pda of the
+    /// program at account index `i`.
+    #[inline(always)]
+    pub fn discriminator(&self) -> u8 {
+        self.discriminator
+    }
+
+    #[inline(always)]
+    pub fn address_config(&self) -> &[u8; 32] {
+        &self.address_config
+    }
+
+    #[inline(always)]
+    pub fn is_signer(&self) -> bool {
+        self.is_signer != 0
+    }
+
+    #[inline(always)]
+    pub fn is_writable(&self) -> bool {
+        self.is_writable != 0
+    }
+}
+
+/// The extra account metas of a mint's transfer hook, read in place.
+///
+/// This holds a shared borrow of the account's data for as long as it
+/// lives.
+pub struct ExtraAccountMetaList<'a> {
+    data: DataRef<'a>,
+    start: usize,
+    len: usize,
+}
+
+impl<'a> ExtraAccountMetaList<'a> {
+    /// Borrows `account`'s data as the extra account metas of `mint`'s
+    /// transfer hook `program_id`.
+    ///
+    /// Fails with [`ProgramError::InvalidSeeds`] if `account` isn't at
+    /// the derived address, with [`ProgramError::IncorrectProgramId`]
+    /// if it isn't owned by `program_id`, and with
+    /// [`ProgramError::InvalidAccountData`] if it holds no well-formed
+    /// `Execute` entry.
+    pub fn from_account_info(
+        account: &'a NoStdAccountInfo,
+        mint: &Pubkey,
+        program_id: &Pubkey,
+    ) -> Result<ExtraAccountMetaList<'a>, ProgramError> {
+        if extra_account_metas_address(mint, program_id).0
+            != *account.key()
+        {
+            return Err(ProgramError::InvalidSeeds);
+        }
+        if account.owner() != program_id {
+            return Err(ProgramError::IncorrectProgramId);
+        }
+        let data = account.try_borrow_data()?;
+
+        // TLV entries of an 8 byte discriminator and a u32 length
+        let mut offset = 0;
+        while let Some(header) = data.get(offset..offset + 12) {
+            let value_len =
+                u32::from_le_bytes(header[8..].try_into().unwrap())
+                    as usize;
+            let value = data
+                .get(offset + 12..offset + 12 + value_len)
+                .ok_or(ProgramError::InvalidAccountData)?;
+            if header[..8] != EXECUTE_DISCRIMINATOR {
+                offset += 12 + value_len;
+                continue;
+            }
+
+            // A u32 count, then the metas
+            let count = value
+                .get(..4)
+                .map(|count| {
+                    u32::from_le_bytes(count.try_into().unwrap())
+                })
+                .ok_or(ProgramError::InvalidAccountData)?
+                as usize;
+            if value.len() < 4 + count * size_of::<ExtraAccountMeta>() {
+                return Err(ProgramError::InvalidAccountData);
+            }
+            return Ok(ExtraAccountMetaList {
+                data,
+                start: offset + 16,
+                len: count,
+            });
+        }
+        Err(ProgramError::InvalidAccountData)
+    }
+
+    #[inline(always)]
+    pub fn metas(&self) -> &[ExtraAccountMeta] {
+        // SAFETY: checked to be in bounds, and `ExtraAccountMeta` is
+        // bytes only
+        unsafe {
+            core::slice::from_raw_parts(
+                self.data.as_ptr().add(self.start)
+                    as *const ExtraAccountMeta,
+                self.len,
+            )
+        }
+    }
+
+    /// Resolves the address of the extra account at `index`, given the
+    /// `Execute` instruction's data and accounts.
+    ///
+    /// `accounts` need only hold the accounts the meta depends on, i.e.
+    /// those before it. Seeds read from account data take a shared
+    /// borrow of it while deriving.
+    pub fn resolve(
+        &self,
+        index: usize,
+        instruction_data: &[u8],
+        accounts: &[NoStdAccountInfo],
+        program_id: &Pubkey,
+    ) -> Result<Pubkey, ProgramError> {
+        let meta = self
+            .metas()
+            .get(index)
+            .ok_or(ProgramError::InvalidArgument)?;
+        let config = meta.address_config();
+        match meta.discriminator() {
+            0 => Ok(Pubkey::new_from_array(*config)),
+            1 => resolve_pda(
+                config,
+                instruction_data,
+                accounts,
+                program_id,
+            ),
+            2 => {
+                resolve_pubkey_data(config, instruction_data, accounts)
+            }
+            discriminator @ 128.. => {
+                let program = accounts
+                    .get((discriminator - 128) as usize)
+                    .ok_or(ProgramError::NotEnoughAccountKeys)?;
+                resolve_pda(
+                    config,
+                    instruction_data,
+                    accounts,
+                    program.key(),
+                )
+            }
+            _ => Err(ProgramError::InvalidAccountData),
+        }
+    }
+
+    /// Checks that the extra accounts of an `Execute` instruction,
+    /// which follow the extra account metas account in `accounts`,
+    /// have the addresses and privileges this list describes.
+    ///
+    /// Fails with [`ProgramError::NotEnoughAccountKeys`] if any are
+    /// missing and with [`ProgramError::InvalidArgument`] if any don't
+    /// match.
+    pub fn check_accounts(
+        &self,
+        instruction_data: &[u8],
+        accounts: &[NoStdAccountInfo],
+        program_id: &Pubkey,
+    ) -> ProgramResult {
+        let extra_accounts = accounts
+            .get(EXTRA_ACCOUNT_METAS_INDEX + 1..)
+            .unwrap_or(&[]);
+        if extra_accounts.len() < self.len {
+            return Err(ProgramError::NotEnoughAccountKeys);
+        }
+
+        for (index, (meta, account)) in self
+            .metas()
+            .iter()
+            .zip(extra_accounts)
+            .enumerate()
+        {
+            let address = self.resolve(
+                index,
+                instruction_data,
+                accounts,
+                program_id,
+            )?;
+            if address != *account.key()
+                || meta.is_signer() != account.is_signer()
+                || meta.is_writable() != account.is_writable()
+            {
+                return Err(ProgramError::InvalidArgument);
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Private: a seed as packed into an address config.
+#[derive(Clone, Copy)]
+enum Seed {
+    /// A range of the address config itself.
+    Literal(usize, usize),
+    /// A range of the instruction data.
+    InstructionData(usize, usize),
+    /// The key of the account at this index.
+    AccountKey(usize),
+    /// A range of the data of the account at this index.
+    AccountData(usize, usize, usize),
+}
+
+/// Private: unpacks the seeds of `config` into `seeds`, returning how
+/// many there are. Seeds end at the end of the config or at a zero
+/// discriminator.
+///
+/// Fails with [`ProgramError::MaxSeedLengthExceeded`] if a seed is
+/// longer than [`MAX_SEED_LEN`], or if there are too many seeds to
+/// append a bump seed to.
+fn unpack_seeds(
+    config: &[u8; 32],
+    seeds: &mut [Seed; MAX_SEEDS],
+) -> Result<usize, ProgramError> {
+    let byte = |offset: usize| {
+        config
+            .get(offset)
+            .map(|&byte| byte as usize)
+            .ok_or(ProgramError::InvalidAccountData)
+    };
+
+    let mut offset = 0;
+    let mut len = 0;
+    while offset < config.len() && config[offset] != 0 {
+        let (seed, size) = match config[offset] {
+            1 => {
+                let seed_len = byte(offset + 1)?;
+                (Seed::Literal(offset + 2, seed_len), 2 + seed_len)
+            }
+            2 => (
+                Seed::InstructionData(
+                    byte(offset + 1)?,
+                    byte(offset + 2)?,
+                ),
+                3,
+            ),
+            3 => (Seed::AccountKey(byte(offset + 1)?), 2),
+            4 => (
+                Seed::AccountData(
+                    byte(offset + 1)?,
+                    byte(offset + 2)?,
+                    byte(offset + 3)?,
+                ),
+                4,
+            ),
+            _ => return Err(ProgramError::InvalidAccountData),
+        };
+        if offset + size > config.len() {
+            return Err(ProgramError::InvalidAccountData);
+        }
+        let seed_len = match seed {
+            Seed::Literal(_, len)
+            | Seed::InstructionData(_, len)
+            | Seed::AccountData(_, _, len) => len,
+            Seed::AccountKey(_) => 32,
+        };
+        if seed_len > MAX_SEED_LEN || len + 1 == MAX_SEEDS {
+            return Err(ProgramError::MaxSeedLengthExceeded);
+        }
+        seeds[len] = seed;
+        len += 1;
+        offset += size;
+    }
+    Ok(len)
+}
+
+/// Private: resolves a pda of `program_id` from the seeds in `config`.
+fn resolve_pda(
+    config: &[u8; 32],
+    instruction_data: &[u8],
+    accounts: &[NoStdAccountInfo],
+    program_id: &Pubkey,
+) -> Result<Pubkey, ProgramError> {
+    let mut seeds = [Seed::AccountKey(0); MAX_SEEDS];
+    let len = unpack_seeds(config, &mut seeds)?;
+    let account = |index: usize| {
+        accounts
+            .get(index)
+            .ok_or(ProgramError::NotEnoughAccountKeys)
+    };
+
+    // Data borrows first, so the seeds below can point into them
+    let mut guards: [Option<DataRef>; MAX_SEEDS] =
+        core::array::from_fn(|_| None);
+    for (guard, seed) in guards.iter_mut().zip(&seeds[..len]) {
+        if let Seed::AccountData(index, ..) = *seed {
+            *guard = Some(account(index)?.try_borrow_data()?);
+        }
+    }
+
+    let mut slices: [&[u8]; MAX_SEEDS] = [&[]; MAX_SEEDS];
+    for ((slice, seed), guard) in slices
+        .iter_mut()
+        .zip(&seeds[..len])
+        .zip(&guards)
+    {
+        *slice = match *seed {
+            Seed::Literal(start, len) => &config[start..start + len],
+            Seed::InstructionData(start, len) => instruction_data
+                .get(start..start + len)
+                .ok_or(ProgramError::InvalidInstructionData)?,
+            Seed::AccountKey(index) => account(index)?.key().as_ref(),
+            Seed::AccountData(_, start, len) => guard
+                .as_deref()
+                .and_then(|data| data.get(start..start + len))
+                .ok_or(ProgramError::InvalidAccountData)?,
+        };
+    }
+
+    try_find_program_address(&slices[..len], program_id)
+        .map(|(address, _)| address)
+        .ok_or(ProgramError::InvalidSeeds)
+}
+
+/// Private: resolves an address read from instruction or account data.
+fn resolve_pubkey_data(
+    config: &[u8; 32],
+    instruction_data: &[u8],
+    accounts: &[NoStdAccountInfo],
+) -> Result<Pubkey, ProgramError> {
+    let read = |data: &[u8], start: usize, error| {
+        data.get(start..start + 32)
+            .map(|bytes| {
+                Pubkey::new_from_array(bytes.try_into().unwrap())
+            })
+            .ok_or(error)
+    };
+    match config[0] {
+        1 => read(
+            instruction_data,
+            config[1] as usize,
+            ProgramError::InvalidInstructionData,
+        ),
+        2 => {
+            let account = accounts
+                .get(config[1] as usize)
+                .ok_or(ProgramError::NotEnoughAccountKeys)?;
+            read(
+                &account.try_borrow_data()?,
+                config[2] as usize,
+                ProgramError::InvalidAccountData,
+            )
+        }
+        _ => Err(ProgramError::InvalidAccountData),
+    }
+}
+
+#[test]
+fn test_extra_account_metas() {
+    extern crate alloc;
+    use {crate::entrypoint_nostd::TestAccount, alloc::vec::Vec};
+
+    assert_eq!(size_of::<ExtraAccountMeta>(), 35);
+
+    let program_id = Pubkey::new_from_array([7; 32]);
+    let mint = Pubkey::new_from_array([2; 32]);
+    let (address, _) = extra_account_metas_address(&mint, &program_id);
+
+    let mut instruction_data = EXECUTE_DISCRIMINATOR.to_vec();
+    instruction_data.extend(1_000_u64.to_le_bytes());
+    assert_eq!(unpack_execute(&instruction_data), Ok(1_000));
+    assert_eq!(
+        unpack_execute(&instruction_data[1..]),
+        Err(ProgramError::InvalidInstructionData)
+    );
+
+    // A fixed address, then a pda of b"counter" and the mint's key
+    let fixed = Pubkey::new_from_array([9; 32]);
+    let mut pda_config = [0; 32];
+    pda_config[..9].copy_from_slice(b"\x01\x07counter");
+    pda_config[9..11].copy_from_slice(&[3, 1]);
+    let pda = Pubkey::find_program_address(
+        &[b"counter", mint.as_ref()],
+        &program_id,
+    )
+    .0;
+
+    let mut metas = Vec::new();
+    metas.extend(EXECUTE_DISCRIMINATOR);
+    metas.extend((4_u32 + 2 * 35).to_le_bytes());
+    metas.extend(2_u32.to_le_bytes());
+    metas.push(0);
+    metas.extend(fixed.to_bytes());
+    metas.extend([0, 0]);
+    metas.push(1);
+    metas.extend(pda_config);
+    metas.extend([0, 1]);
+
+    // Source, mint, destination, authority, the list, then the extras
+    let keys = [
+        Pubkey::default(),
+        mint,
+        Pubkey::default(),
+        Pubkey::default(),
+        address,
+        fixed,
+        pda,
+    ];
+    let mut buffers = Vec::new();
+    let accounts: Vec<NoStdAccountInfo> = keys
+        .iter()
+        .enumerate()
+        .map(|(i, key)| {
+            let data_len = if i == 4 { metas.len() } else { 0 };
+            let (buffer, account) = TestAccount {
+                key: *key,
+                owner: program_id,
+                // Only the pda is writable
+                is_writable: i == 6,
+                data_len,
+                ..Default::default()
+            }
+            .build();
+            account
+                .try_borrow_mut_data()
+                .unwrap()
+                .copy_from_slice(&metas[..data_len]);
+            buffers.push(buffer);
+            account
+        })
+        .collect();
+
+    let list = ExtraAccountMetaList::from_account_info(
+        &accounts[4],
+        &mint,
+        &program_id,
+    )
+    .unwrap();
+    assert_eq!(list.metas().len(), 2);
+    assert_eq!(
+        list.resolve(1, &instruction_data, &accounts, &program_id),
+        Ok(pda)
+    );
+    assert_eq!(
+        list.check_accounts(&instruction_data, &accounts, &program_id),
+        Ok(())
+    );
+    assert_eq!(
+        list.check_accounts(
+            &instruction_data,
+            &accounts[..6],
+            &program_id
+        ),
+        Err(ProgramError::NotEnoughAccountKeys)
+    );
+    assert_eq!(
+        list.check_accounts(
+            &instruction_data,
+            &[
+                &accounts[..5],
+                &[accounts[6].clone(), accounts[5].clone()]
+            ]
+            .concat(),
+            &program_id
+        ),
+        Err(ProgramError::InvalidArgument)
+    );
+
+    // Only the mint's own list is accepted
+    assert_eq!(
+        ExtraAccountMetaList::from_account_info(
+            &accounts[4],
+            &fixed,
+            &program_id
+        )
+        .err(),
+        Some(ProgramError::InvalidSeeds)
+    );
+
+    // Oversized or too many seeds are rejected, not panicked on
+    let mut oversized = [0; 32];
+    oversized[..3].copy_from_slice(&[2, 0, MAX_SEED_LEN as u8 + 1]);
+    assert_eq!(
+        resolve_pda(&oversized, &[0; 64], &accounts, &program_id),
+        Err(ProgramError::MaxSeedLengthExceeded)
+    );
+    let too_many = [3, 0].repeat(16).try_into().unwrap();
+    assert_eq!(
+        resolve_pda(
+            &too_many,
+            &instruction_data,
+            &accounts,
+            &program_id
+        ),
+        Err(ProgramError::MaxSeedLengthExceeded)
+    );
+}